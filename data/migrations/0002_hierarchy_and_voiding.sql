@@ -0,0 +1,7 @@
+-- Nested and archived accounts
+ALTER TABLE "Accounts" ADD COLUMN "parent" INTEGER REFERENCES "Accounts"("id");
+ALTER TABLE "Accounts" ADD COLUMN "description" TEXT;
+ALTER TABLE "Accounts" ADD COLUMN "archived" INTEGER NOT NULL DEFAULT 0;
+
+-- A reversal points to the transaction it voids
+ALTER TABLE "Transactions" ADD COLUMN "reverses" INTEGER REFERENCES "Transactions"("id") ON DELETE CASCADE;
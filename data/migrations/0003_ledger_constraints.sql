@@ -0,0 +1,83 @@
+-- Double entry invariants enforced by the database.
+-- Requires PRAGMA foreign_keys = ON, which is set on every connection.
+
+-- Always empty. Every row in TransactionViolations references it through a
+-- deferred foreign key, so a commit fails while any violation is left
+CREATE TABLE IF NOT EXISTS "CommitBlock" (
+	"id"	INTEGER PRIMARY KEY
+);
+
+-- Transactions that are currently unbalanced or mix currencies
+CREATE TABLE IF NOT EXISTS "TransactionViolations" (
+	"transaction_id"	INTEGER NOT NULL,
+	"reason"	TEXT NOT NULL,
+	"block"	INTEGER NOT NULL DEFAULT 1 REFERENCES "CommitBlock"("id") DEFERRABLE INITIALLY DEFERRED,
+	PRIMARY KEY("transaction_id", "reason")
+);
+
+-- Debits - Credits and the number of currencies of every transaction with entries
+CREATE VIEW IF NOT EXISTS "TransactionChecks" AS
+SELECT e.transaction_id, SUM(e.amount) as imbalance, COUNT(DISTINCT a.currency) as currencies FROM
+	(SELECT transaction_id, account, balance as amount FROM Debits
+	UNION ALL
+	SELECT transaction_id, account, -balance as amount FROM Credits) as e
+LEFT JOIN Accounts as a ON a.id = e.account
+GROUP BY e.transaction_id;
+
+CREATE TRIGGER IF NOT EXISTS "debits_after_insert" AFTER INSERT ON "Debits" BEGIN
+	DELETE FROM TransactionViolations WHERE transaction_id = NEW.transaction_id;
+	INSERT INTO TransactionViolations (transaction_id, reason)
+		SELECT transaction_id, 'unbalanced' FROM TransactionChecks WHERE transaction_id = NEW.transaction_id AND imbalance <> 0
+		UNION ALL
+		SELECT transaction_id, 'mixed_currencies' FROM TransactionChecks WHERE transaction_id = NEW.transaction_id AND currencies > 1;
+END;
+
+CREATE TRIGGER IF NOT EXISTS "credits_after_insert" AFTER INSERT ON "Credits" BEGIN
+	DELETE FROM TransactionViolations WHERE transaction_id = NEW.transaction_id;
+	INSERT INTO TransactionViolations (transaction_id, reason)
+		SELECT transaction_id, 'unbalanced' FROM TransactionChecks WHERE transaction_id = NEW.transaction_id AND imbalance <> 0
+		UNION ALL
+		SELECT transaction_id, 'mixed_currencies' FROM TransactionChecks WHERE transaction_id = NEW.transaction_id AND currencies > 1;
+END;
+
+CREATE TRIGGER IF NOT EXISTS "debits_after_delete" AFTER DELETE ON "Debits" BEGIN
+	DELETE FROM TransactionViolations WHERE transaction_id = OLD.transaction_id;
+	INSERT INTO TransactionViolations (transaction_id, reason)
+		SELECT transaction_id, 'unbalanced' FROM TransactionChecks WHERE transaction_id = OLD.transaction_id AND imbalance <> 0
+		UNION ALL
+		SELECT transaction_id, 'mixed_currencies' FROM TransactionChecks WHERE transaction_id = OLD.transaction_id AND currencies > 1;
+END;
+
+CREATE TRIGGER IF NOT EXISTS "credits_after_delete" AFTER DELETE ON "Credits" BEGIN
+	DELETE FROM TransactionViolations WHERE transaction_id = OLD.transaction_id;
+	INSERT INTO TransactionViolations (transaction_id, reason)
+		SELECT transaction_id, 'unbalanced' FROM TransactionChecks WHERE transaction_id = OLD.transaction_id AND imbalance <> 0
+		UNION ALL
+		SELECT transaction_id, 'mixed_currencies' FROM TransactionChecks WHERE transaction_id = OLD.transaction_id AND currencies > 1;
+END;
+
+-- An update can move an entry between transactions, so both are checked
+CREATE TRIGGER IF NOT EXISTS "debits_after_update" AFTER UPDATE ON "Debits" BEGIN
+	DELETE FROM TransactionViolations WHERE transaction_id IN (OLD.transaction_id, NEW.transaction_id);
+	INSERT INTO TransactionViolations (transaction_id, reason)
+		SELECT transaction_id, 'unbalanced' FROM TransactionChecks WHERE transaction_id IN (OLD.transaction_id, NEW.transaction_id) AND imbalance <> 0
+		UNION ALL
+		SELECT transaction_id, 'mixed_currencies' FROM TransactionChecks WHERE transaction_id IN (OLD.transaction_id, NEW.transaction_id) AND currencies > 1;
+END;
+
+CREATE TRIGGER IF NOT EXISTS "credits_after_update" AFTER UPDATE ON "Credits" BEGIN
+	DELETE FROM TransactionViolations WHERE transaction_id IN (OLD.transaction_id, NEW.transaction_id);
+	INSERT INTO TransactionViolations (transaction_id, reason)
+		SELECT transaction_id, 'unbalanced' FROM TransactionChecks WHERE transaction_id IN (OLD.transaction_id, NEW.transaction_id) AND imbalance <> 0
+		UNION ALL
+		SELECT transaction_id, 'mixed_currencies' FROM TransactionChecks WHERE transaction_id IN (OLD.transaction_id, NEW.transaction_id) AND currencies > 1;
+END;
+
+-- Changing the currency of an account with entries would mix currencies
+-- in all of its transactions at once
+CREATE TRIGGER IF NOT EXISTS "accounts_currency_locked" BEFORE UPDATE OF "currency" ON "Accounts"
+WHEN NEW.currency <> OLD.currency
+	AND (EXISTS(SELECT 1 FROM Debits WHERE account = OLD.id) OR EXISTS(SELECT 1 FROM Credits WHERE account = OLD.id))
+BEGIN
+	SELECT RAISE(ABORT, 'The currency of an account with entries can not be changed');
+END;
@@ -0,0 +1,78 @@
+-- Transactions across currencies. Every currency of a transaction has to
+-- balance on its own, the legs in different currencies are balanced through
+-- the FX account of each currency at a recorded exchange rate.
+
+-- 1 base = rate quote, e.g. GBP/EUR at 1.15
+CREATE TABLE IF NOT EXISTS "ExchangeRates" (
+	"id"	INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	"base"	TEXT NOT NULL,
+	"quote"	TEXT NOT NULL,
+	"date"	TEXT NOT NULL,
+	"rate"	REAL NOT NULL CHECK("rate" > 0),
+	"source"	TEXT,
+	UNIQUE("base", "quote", "date"),
+	CHECK("base" <> "quote"),
+	FOREIGN KEY("base") REFERENCES "Currency"("code"),
+	FOREIGN KEY("quote") REFERENCES "Currency"("code")
+);
+
+-- The trading account that takes the other side of every leg in a currency
+CREATE TABLE IF NOT EXISTS "FxAccounts" (
+	"currency"	TEXT NOT NULL PRIMARY KEY,
+	"account"	INTEGER NOT NULL UNIQUE,
+	FOREIGN KEY("currency") REFERENCES "Currency"("code"),
+	FOREIGN KEY("account") REFERENCES "Accounts"("id")
+);
+
+-- Debits - Credits of every currency of every transaction with entries
+DROP VIEW IF EXISTS "TransactionChecks";
+CREATE VIEW "TransactionChecks" AS
+SELECT e.transaction_id, a.currency, SUM(e.amount) as imbalance FROM
+	(SELECT transaction_id, account, balance as amount FROM Debits
+	UNION ALL
+	SELECT transaction_id, account, -balance as amount FROM Credits) as e
+LEFT JOIN Accounts as a ON a.id = e.account
+GROUP BY e.transaction_id, a.currency;
+
+DROP TRIGGER IF EXISTS "debits_after_insert";
+CREATE TRIGGER "debits_after_insert" AFTER INSERT ON "Debits" BEGIN
+	DELETE FROM TransactionViolations WHERE transaction_id = NEW.transaction_id;
+	INSERT INTO TransactionViolations (transaction_id, reason)
+		SELECT DISTINCT transaction_id, 'unbalanced' FROM TransactionChecks WHERE transaction_id = NEW.transaction_id AND imbalance <> 0;
+END;
+
+DROP TRIGGER IF EXISTS "credits_after_insert";
+CREATE TRIGGER "credits_after_insert" AFTER INSERT ON "Credits" BEGIN
+	DELETE FROM TransactionViolations WHERE transaction_id = NEW.transaction_id;
+	INSERT INTO TransactionViolations (transaction_id, reason)
+		SELECT DISTINCT transaction_id, 'unbalanced' FROM TransactionChecks WHERE transaction_id = NEW.transaction_id AND imbalance <> 0;
+END;
+
+DROP TRIGGER IF EXISTS "debits_after_delete";
+CREATE TRIGGER "debits_after_delete" AFTER DELETE ON "Debits" BEGIN
+	DELETE FROM TransactionViolations WHERE transaction_id = OLD.transaction_id;
+	INSERT INTO TransactionViolations (transaction_id, reason)
+		SELECT DISTINCT transaction_id, 'unbalanced' FROM TransactionChecks WHERE transaction_id = OLD.transaction_id AND imbalance <> 0;
+END;
+
+DROP TRIGGER IF EXISTS "credits_after_delete";
+CREATE TRIGGER "credits_after_delete" AFTER DELETE ON "Credits" BEGIN
+	DELETE FROM TransactionViolations WHERE transaction_id = OLD.transaction_id;
+	INSERT INTO TransactionViolations (transaction_id, reason)
+		SELECT DISTINCT transaction_id, 'unbalanced' FROM TransactionChecks WHERE transaction_id = OLD.transaction_id AND imbalance <> 0;
+END;
+
+DROP TRIGGER IF EXISTS "debits_after_update";
+-- An update can move an entry between transactions, so both are checked
+CREATE TRIGGER "debits_after_update" AFTER UPDATE ON "Debits" BEGIN
+	DELETE FROM TransactionViolations WHERE transaction_id IN (OLD.transaction_id, NEW.transaction_id);
+	INSERT INTO TransactionViolations (transaction_id, reason)
+		SELECT DISTINCT transaction_id, 'unbalanced' FROM TransactionChecks WHERE transaction_id IN (OLD.transaction_id, NEW.transaction_id) AND imbalance <> 0;
+END;
+
+DROP TRIGGER IF EXISTS "credits_after_update";
+CREATE TRIGGER "credits_after_update" AFTER UPDATE ON "Credits" BEGIN
+	DELETE FROM TransactionViolations WHERE transaction_id IN (OLD.transaction_id, NEW.transaction_id);
+	INSERT INTO TransactionViolations (transaction_id, reason)
+		SELECT DISTINCT transaction_id, 'unbalanced' FROM TransactionChecks WHERE transaction_id IN (OLD.transaction_id, NEW.transaction_id) AND imbalance <> 0;
+END;
@@ -0,0 +1,2 @@
+-- A transaction can only be voided once
+CREATE UNIQUE INDEX IF NOT EXISTS "Transactions_reverses" ON "Transactions"("reverses");
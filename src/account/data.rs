@@ -1,4 +1,14 @@
+use crate::datastruct::parse_date;
+use crate::error::Result as LedgerResult;
+use crate::money::{self, Money};
+use crate::transaction::data::EntryType;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Utc};
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
 use serde_derive::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::str::FromStr;
 
 #[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Copy, Clone)]
 pub enum AccountType {
@@ -11,18 +21,99 @@ pub enum AccountType {
     Losses,
 }
 impl AccountType {
-    pub fn from_i32(value: i32) -> AccountType {
+    pub fn from_i32(value: i32) -> Option<AccountType> {
         match value {
-            0 => AccountType::Assets,
-            1 => AccountType::Liabilities,
-            2 => AccountType::Equities,
-            3 => AccountType::Revenue,
-            4 => AccountType::Expenses,
-            5 => AccountType::Gains,
-            6 => AccountType::Losses,
-            _ => panic!("Unknown value: {}", value),
+            0 => Some(AccountType::Assets),
+            1 => Some(AccountType::Liabilities),
+            2 => Some(AccountType::Equities),
+            3 => Some(AccountType::Revenue),
+            4 => Some(AccountType::Expenses),
+            5 => Some(AccountType::Gains),
+            6 => Some(AccountType::Losses),
+            _ => None,
         }
     }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            AccountType::Assets => "Assets",
+            AccountType::Liabilities => "Liabilities",
+            AccountType::Equities => "Equities",
+            AccountType::Revenue => "Revenue",
+            AccountType::Expenses => "Expenses",
+            AccountType::Gains => "Gains",
+            AccountType::Losses => "Losses",
+        }
+    }
+
+    // Assets, Expenses and Losses increase with a debit.
+    // Liabilities, Equities, Revenue and Gains increase with a credit
+    pub fn debit_normal(self) -> bool {
+        match self {
+            AccountType::Assets | AccountType::Expenses | AccountType::Losses => true,
+            AccountType::Liabilities
+            | AccountType::Equities
+            | AccountType::Revenue
+            | AccountType::Gains => false,
+        }
+    }
+
+    // Converts a raw debits - credits balance into the balance
+    // shown on the normal side of the account
+    pub fn normal_balance(self, raw: i64) -> LedgerResult<i64> {
+        if self.debit_normal() {
+            return Ok(raw);
+        }
+
+        money::neg(raw)
+    }
+
+    // The same as normal_balance, keeping the currency of the amount
+    pub fn normal_money(self, raw: &Money) -> LedgerResult<Money> {
+        Ok(raw.with_amount(self.normal_balance(raw.amount)?))
+    }
+}
+
+// Parses the name of an account type, ignoring case
+impl FromStr for AccountType {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<AccountType, String> {
+        let types = [
+            AccountType::Assets,
+            AccountType::Liabilities,
+            AccountType::Equities,
+            AccountType::Revenue,
+            AccountType::Expenses,
+            AccountType::Gains,
+            AccountType::Losses,
+        ];
+
+        types
+            .iter()
+            .find(|t| t.name().eq_ignore_ascii_case(value.trim()))
+            .cloned()
+            .ok_or_else(|| format!("Unknown account type: {}", value))
+    }
+}
+
+// Account types are stored by number. An unknown stored number
+// is returned as an out of range error instead of a panic
+impl FromSql for AccountType {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<AccountType> {
+        let raw = value.as_i64()?;
+
+        i32::try_from(raw)
+            .ok()
+            .and_then(AccountType::from_i32)
+            .ok_or(FromSqlError::OutOfRange(raw))
+    }
+}
+
+impl ToSql for AccountType {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(*self as i32))
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -31,6 +122,10 @@ pub struct Account {
     pub acc_type: AccountType,
     pub name: String,
     pub currency: String,
+    pub parent: Option<i32>,
+    pub description: Option<String>,
+    // Archived accounts keep their history but can't receive new entries
+    pub archived: bool,
 }
 
 impl Account {
@@ -40,6 +135,12 @@ impl Account {
         }
         true
     }
+
+    // A child has to share the type of its parent, and its currency
+    // so that the balances can be rolled up into the parent
+    pub fn can_parent(&self, acc_type: AccountType, currency: &str) -> bool {
+        self.acc_type == acc_type && self.currency == currency
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -47,15 +148,467 @@ pub struct DetailedAccount {
     pub id: i32,
     pub acc_type: AccountType,
     pub name: String,
-    pub balance: i32,
+    // Debits - Credits
+    pub balance: Money,
+    // Balance on the normal side of the account
+    pub normal_balance: Money,
     pub currency: String,
+    pub parent: Option<i32>,
+    pub archived: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AccountBalance {
+    pub id: i32,
+    pub acc_type: AccountType,
+    pub currency: String,
+    // Debits - Credits
+    pub value: Money,
+    // Balance on the normal side of the account
+    pub normal_balance: Money,
+}
+
+// An account type given either by name, e.g. "Assets", or by number
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum AccountTypeValue {
+    Number(i64),
+    Name(String),
+}
+
+impl AccountTypeValue {
+    pub fn account_type(&self) -> Result<AccountType, String> {
+        match self {
+            AccountTypeValue::Number(v) => i32::try_from(*v)
+                .ok()
+                .and_then(AccountType::from_i32)
+                .ok_or_else(|| format!("Unknown account type: {}", v)),
+            AccountTypeValue::Name(v) => v.parse(),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct NewAccount {
-    pub acc_type: i32,
+    pub acc_type: AccountTypeValue,
     pub name: String,
     pub currency: String,
+    pub parent: Option<i32>,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateAccount {
+    pub name: String,
+    pub description: Option<String>,
+}
+
+impl UpdateAccount {
+    pub fn valid(&self) -> bool {
+        !self.name.trim().is_empty()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ArchivedQuery {
+    archived: Option<bool>,
+}
+
+impl ArchivedQuery {
+    // Archived accounts are hidden unless asked for with archived=true
+    pub fn include_archived(&self) -> bool {
+        self.archived.unwrap_or(false)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Deserialize, Copy, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum AccountSort {
+    Id,
+    Name,
+    Type,
+    Balance,
+}
+
+#[derive(Debug, PartialEq, Eq, Deserialize, Copy, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+// Filters for the account listing.
+// type takes a comma separated list of account type names
+#[derive(Debug, Deserialize)]
+pub struct AccountQuery {
+    #[serde(rename = "type")]
+    types: Option<String>,
+    q: Option<String>,
+    sort: Option<AccountSort>,
+    order: Option<SortOrder>,
+    archived: Option<bool>,
+}
+
+impl AccountQuery {
+    // A query sorted by id, for callers outside of the HTTP API
+    pub fn new(types: Option<String>, q: Option<String>, archived: bool) -> AccountQuery {
+        AccountQuery {
+            types,
+            q,
+            sort: None,
+            order: None,
+            archived: Some(archived),
+        }
+    }
+
+    // None when every account type should be listed
+    pub fn types(&self) -> Result<Option<Vec<AccountType>>, String> {
+        match &self.types {
+            Some(v) => v
+                .split(',')
+                .filter(|t| !t.trim().is_empty())
+                .map(AccountType::from_str)
+                .collect::<Result<Vec<AccountType>, String>>()
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn include_archived(&self) -> bool {
+        self.archived.unwrap_or(false)
+    }
+
+    // Filters by type and name and sorts the accounts.
+    // Sorting by balance uses the balance on the normal side of the account
+    pub fn apply(&self, accounts: Vec<DetailedAccount>) -> Vec<DetailedAccount> {
+        let types = self.types().ok().flatten();
+        let search = self.q.as_ref().map(|q| q.trim().to_lowercase());
+
+        let mut result: Vec<DetailedAccount> = accounts
+            .into_iter()
+            .filter(|a| match &types {
+                Some(t) => t.contains(&a.acc_type),
+                None => true,
+            })
+            .filter(|a| match &search {
+                Some(q) => a.name.to_lowercase().contains(q.as_str()),
+                None => true,
+            })
+            .collect();
+
+        let sort = self.sort.unwrap_or(AccountSort::Id);
+        result.sort_by(|a, b| {
+            let ordering: Ordering = match sort {
+                AccountSort::Id => a.id.cmp(&b.id),
+                AccountSort::Name => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+                AccountSort::Type => (a.acc_type as i32).cmp(&(b.acc_type as i32)),
+                AccountSort::Balance => a.normal_balance.amount.cmp(&b.normal_balance.amount),
+            };
+
+            ordering.then(a.id.cmp(&b.id))
+        });
+
+        if self.order == Some(SortOrder::Desc) {
+            result.reverse();
+        }
+
+        result
+    }
+}
+
+// Adds the balance of every account to each of its ancestors.
+// Takes (id, parent, balance) and returns the rolled up balance of every id
+pub fn rolled_up_balances(accounts: &[(i32, Option<i32>, i64)]) -> LedgerResult<HashMap<i32, i64>> {
+    let parents: HashMap<i32, Option<i32>> = accounts.iter().map(|a| (a.0, a.1)).collect();
+    let mut rolled: HashMap<i32, i64> = accounts.iter().map(|a| (a.0, 0)).collect();
+
+    for (id, _, balance) in accounts {
+        let mut current = Some(*id);
+        // The depth guard stops a corrupt parent cycle from looping forever
+        let mut depth = 0;
+
+        while let Some(v) = current {
+            match rolled.get_mut(&v) {
+                Some(total) => *total = money::add(*total, *balance)?,
+                None => break,
+            }
+
+            depth += 1;
+            if depth > accounts.len() {
+                break;
+            }
+
+            current = parents.get(&v).cloned().flatten();
+        }
+    }
+
+    Ok(rolled)
+}
+
+#[derive(Debug, Serialize)]
+pub struct AccountNode {
+    pub id: i32,
+    pub acc_type: AccountType,
+    pub name: String,
+    pub currency: String,
+    pub balance: Money,
+    pub normal_balance: Money,
+    // Balance of the account and all of its descendants
+    pub rolled_up_balance: Money,
+    pub normal_rolled_up_balance: Money,
+    pub children: Vec<AccountNode>,
+}
+
+impl AccountNode {
+    // Builds the chart of accounts. Accounts without a parent,
+    // or with a parent that is not in the list, become roots
+    pub fn tree(accounts: Vec<DetailedAccount>) -> LedgerResult<Vec<AccountNode>> {
+        let balances: Vec<(i32, Option<i32>, i64)> = accounts
+            .iter()
+            .map(|a| (a.id, a.parent, a.balance.amount))
+            .collect();
+        let rolled = rolled_up_balances(&balances)?;
+
+        let ids: Vec<i32> = accounts.iter().map(|a| a.id).collect();
+        let mut children: HashMap<Option<i32>, Vec<DetailedAccount>> = HashMap::new();
+
+        for account in accounts {
+            let parent = account
+                .parent
+                .filter(|p| ids.contains(p) && *p != account.id);
+            children.entry(parent).or_default().push(account);
+        }
+
+        AccountNode::build(None, &mut children, &rolled)
+    }
+
+    fn build(
+        parent: Option<i32>,
+        children: &mut HashMap<Option<i32>, Vec<DetailedAccount>>,
+        rolled: &HashMap<i32, i64>,
+    ) -> LedgerResult<Vec<AccountNode>> {
+        let mut accounts = children.remove(&parent).unwrap_or_default();
+        accounts.sort_by(|a, b| a.name.cmp(&b.name));
+
+        accounts
+            .into_iter()
+            .map(|account| {
+                let rolled_up_balance = account
+                    .balance
+                    .with_amount(rolled.get(&account.id).cloned().unwrap_or(0));
+
+                Ok(AccountNode {
+                    id: account.id,
+                    acc_type: account.acc_type,
+                    name: account.name,
+                    currency: account.currency,
+                    normal_rolled_up_balance: account.acc_type.normal_money(&rolled_up_balance)?,
+                    balance: account.balance,
+                    normal_balance: account.normal_balance,
+                    rolled_up_balance,
+                    children: AccountNode::build(Some(account.id), children, rolled)?,
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Copy, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum Interval {
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+impl Interval {
+    // The start of the period containing the date. Weeks start on Monday
+    fn period_start(self, date: NaiveDate) -> NaiveDate {
+        match self {
+            Interval::Daily => date,
+            Interval::Weekly => {
+                date - Duration::days(i64::from(date.weekday().num_days_from_monday()))
+            }
+            Interval::Monthly => NaiveDate::from_ymd(date.year(), date.month(), 1),
+        }
+    }
+
+    fn next_period(self, start: NaiveDate) -> NaiveDate {
+        match self {
+            Interval::Daily => start + Duration::days(1),
+            Interval::Weekly => start + Duration::weeks(1),
+            Interval::Monthly => {
+                if start.month() == 12 {
+                    NaiveDate::from_ymd(start.year() + 1, 1, 1)
+                } else {
+                    NaiveDate::from_ymd(start.year(), start.month() + 1, 1)
+                }
+            }
+        }
+    }
+
+    // The start of every period between the two dates, in order
+    pub fn periods(self, from: DateTime<Utc>, to: DateTime<Utc>) -> Vec<NaiveDate> {
+        self.period_starts(from, to).collect()
+    }
+
+    // Lazy version of periods, so a range can be measured without building it
+    pub fn period_starts(
+        self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> impl Iterator<Item = NaiveDate> {
+        let first = self.period_start(from.naive_utc().date());
+        let last = to.naive_utc().date();
+
+        std::iter::successors(Some(first), move |start| Some(self.next_period(*start)))
+            .take_while(move |start| *start <= last)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IntervalQuery {
+    interval: Option<Interval>,
+}
+
+impl IntervalQuery {
+    // Defaults to monthly buckets
+    pub fn interval(&self) -> Interval {
+        self.interval.unwrap_or(Interval::Monthly)
+    }
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct BalancePoint {
+    // The start of the period
+    pub date: NaiveDate,
+    // Debits - Credits at the close of the period
+    pub value: Money,
+    pub normal_balance: Money,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BalanceHistory {
+    pub id: i32,
+    pub acc_type: AccountType,
+    pub currency: String,
+    pub interval: Interval,
+    pub points: Vec<BalancePoint>,
+}
+
+impl BalanceHistory {
+    // Roughly ten years of daily balances
+    pub const MAX_POINTS: usize = 3660;
+
+    // Builds the closing balance of every period from the balance at the start
+    // of the range and the movements within it, which have to be in date order.
+    // The last period closes at the end of the range
+    pub fn new(
+        account: &Account,
+        interval: Interval,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        opening: &Money,
+        movements: &[(DateTime<Utc>, i64)],
+    ) -> LedgerResult<BalanceHistory> {
+        let periods = interval.periods(from, to);
+        let mut balance = opening.amount;
+        let mut remaining = movements.iter().peekable();
+        let mut points = Vec::new();
+
+        for (i, start) in periods.iter().enumerate() {
+            let close = Utc
+                .from_utc_date(&interval.next_period(*start))
+                .and_hms(0, 0, 0);
+            let last = i == periods.len() - 1;
+
+            while let Some((date, amount)) = remaining.peek() {
+                if !last && *date >= close {
+                    break;
+                }
+                balance = money::add(balance, *amount)?;
+                remaining.next();
+            }
+
+            let value = opening.with_amount(balance);
+
+            points.push(BalancePoint {
+                date: *start,
+                normal_balance: account.acc_type.normal_money(&value)?,
+                value,
+            });
+        }
+
+        Ok(BalanceHistory {
+            id: account.id,
+            acc_type: account.acc_type,
+            currency: account.currency.clone(),
+            interval,
+            points,
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct RegisterEntry {
+    pub id: i32,
+    pub transaction_id: i32,
+    pub transaction_name: String,
+    pub date: DateTime<Utc>,
+    pub entry_type: EntryType,
+    pub balance: Money,
+    // Accounts on the other side of the transaction
+    pub counterparts: Vec<String>,
+    // Debits - Credits after this entry, counting every earlier entry
+    pub running_balance: Money,
+    pub normal_running_balance: Money,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AccountRegister {
+    pub id: i32,
+    pub currency: String,
+    pub limit: u32,
+    pub offset: u32,
+    pub entries: Vec<RegisterEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RegisterQuery {
+    from: Option<String>,
+    to: Option<String>,
+    limit: Option<u32>,
+    offset: Option<u32>,
+}
+
+impl RegisterQuery {
+    pub const DEFAULT_LIMIT: u32 = 50;
+    pub const MAX_LIMIT: u32 = 500;
+
+    // Returns true if the supplied dates are valid timestamps. The range and
+    // the page size are checked by the ledger
+    pub fn valid_dates(&self) -> bool {
+        let from_valid = self.from.as_deref().is_none_or(|v| parse_date(v).is_some());
+        let to_valid = self.to.as_deref().is_none_or(|v| parse_date(v).is_some());
+
+        from_valid && to_valid
+    }
+
+    pub fn from(&self) -> Option<DateTime<Utc>> {
+        self.from.as_ref().and_then(|v| parse_date(v))
+    }
+
+    pub fn to(&self) -> Option<DateTime<Utc>> {
+        self.to.as_ref().and_then(|v| parse_date(v))
+    }
+
+    pub fn limit(&self) -> u32 {
+        self.limit.unwrap_or(RegisterQuery::DEFAULT_LIMIT)
+    }
+
+    pub fn offset(&self) -> u32 {
+        self.offset.unwrap_or(0)
+    }
 }
 
 #[cfg(test)]
@@ -64,13 +617,13 @@ mod tests {
 
     #[test]
     fn accounttype_correctly_types() {
-        let asset = AccountType::from_i32(0);
-        let liabilities = AccountType::from_i32(1);
-        let equities = AccountType::from_i32(2);
-        let revenue = AccountType::from_i32(3);
-        let expenses = AccountType::from_i32(4);
-        let gains = AccountType::from_i32(5);
-        let losses = AccountType::from_i32(6);
+        let asset = AccountType::from_i32(0).unwrap();
+        let liabilities = AccountType::from_i32(1).unwrap();
+        let equities = AccountType::from_i32(2).unwrap();
+        let revenue = AccountType::from_i32(3).unwrap();
+        let expenses = AccountType::from_i32(4).unwrap();
+        let gains = AccountType::from_i32(5).unwrap();
+        let losses = AccountType::from_i32(6).unwrap();
 
         assert_eq!(asset, AccountType::Assets);
         assert_eq!(liabilities, AccountType::Liabilities);
@@ -80,4 +633,292 @@ mod tests {
         assert_eq!(gains, AccountType::Gains);
         assert_eq!(losses, AccountType::Losses)
     }
+
+    #[test]
+    fn normal_balance_follows_the_account_side() {
+        assert_eq!(AccountType::Assets.normal_balance(500).unwrap(), 500);
+        assert_eq!(AccountType::Expenses.normal_balance(500).unwrap(), 500);
+        assert_eq!(AccountType::Losses.normal_balance(-20).unwrap(), -20);
+        assert_eq!(AccountType::Liabilities.normal_balance(-500).unwrap(), 500);
+        assert_eq!(AccountType::Equities.normal_balance(-500).unwrap(), 500);
+        assert_eq!(AccountType::Revenue.normal_balance(-500).unwrap(), 500);
+        assert_eq!(AccountType::Gains.normal_balance(30).unwrap(), -30);
+    }
+
+    #[test]
+    fn interval_periods_cover_the_range() {
+        let from = Utc.ymd(2020, 1, 15).and_hms(12, 0, 0);
+        let to = Utc.ymd(2020, 3, 2).and_hms(12, 0, 0);
+
+        let monthly = Interval::Monthly.periods(from, to);
+        let weekly = Interval::Weekly.periods(from, to);
+        let daily = Interval::Daily.periods(from, to);
+
+        assert_eq!(
+            monthly,
+            vec![
+                NaiveDate::from_ymd(2020, 1, 1),
+                NaiveDate::from_ymd(2020, 2, 1),
+                NaiveDate::from_ymd(2020, 3, 1),
+            ]
+        );
+        // 2020-01-13 is a Monday, 2020-03-02 is a Monday
+        assert_eq!(weekly[0], NaiveDate::from_ymd(2020, 1, 13));
+        assert_eq!(weekly.last(), Some(&NaiveDate::from_ymd(2020, 3, 2)));
+        assert_eq!(daily.len(), 48);
+        assert_eq!(
+            Interval::Daily.period_starts(from, to).nth(47),
+            daily.last().cloned()
+        );
+        assert_eq!(Interval::Daily.period_starts(from, to).nth(48), None);
+    }
+
+    #[test]
+    fn balance_history_closes_every_period() {
+        let account = Account {
+            id: 1,
+            acc_type: AccountType::Liabilities,
+            name: String::from("Credit Card"),
+            currency: String::from("GBP"),
+            parent: None,
+            description: None,
+            archived: false,
+        };
+        let movements = vec![
+            (Utc.ymd(2020, 1, 20).and_hms(10, 0, 0), -1000),
+            (Utc.ymd(2020, 1, 31).and_hms(23, 59, 59), -500),
+            (Utc.ymd(2020, 2, 1).and_hms(0, 0, 0), 1500),
+            (Utc.ymd(2020, 3, 10).and_hms(0, 0, 0), -200),
+        ];
+
+        let history = BalanceHistory::new(
+            &account,
+            Interval::Monthly,
+            Utc.ymd(2020, 1, 15).and_hms(0, 0, 0),
+            Utc.ymd(2020, 3, 15).and_hms(0, 0, 0),
+            &Money::new(-300, "GBP", 2),
+            &movements,
+        )
+        .unwrap();
+
+        let closing: Vec<i64> = history.points.iter().map(|p| p.value.amount).collect();
+
+        assert_eq!(closing, vec![-1800, -300, -500]);
+        assert_eq!(history.points[0].normal_balance.formatted(), "18.00");
+    }
+
+    #[test]
+    fn register_query_validates_dates() {
+        let defaults = RegisterQuery {
+            from: None,
+            to: None,
+            limit: None,
+            offset: None,
+        };
+        let malformed = RegisterQuery {
+            from: Some(String::from("2020-02-01")),
+            to: None,
+            limit: None,
+            offset: None,
+        };
+
+        assert!(defaults.valid_dates());
+        assert_eq!(defaults.limit(), RegisterQuery::DEFAULT_LIMIT);
+        assert_eq!(defaults.offset(), 0);
+        assert!(!malformed.valid_dates());
+    }
+
+    fn detailed(id: i32, name: &str, parent: Option<i32>, balance: i64) -> DetailedAccount {
+        DetailedAccount {
+            id,
+            acc_type: AccountType::Expenses,
+            name: String::from(name),
+            balance: Money::new(balance, "GBP", 2),
+            normal_balance: Money::new(balance, "GBP", 2),
+            currency: String::from("GBP"),
+            parent,
+            archived: false,
+        }
+    }
+
+    #[test]
+    fn rolled_up_balances_include_every_descendant() {
+        // Expenses:Food:Groceries and Expenses:Food:Restaurants
+        let accounts = vec![
+            (1, None, 0),
+            (2, Some(1), 100),
+            (3, Some(2), 250),
+            (4, Some(2), 50),
+        ];
+
+        let rolled = rolled_up_balances(&accounts).unwrap();
+
+        assert_eq!(rolled[&1], 400);
+        assert_eq!(rolled[&2], 400);
+        assert_eq!(rolled[&3], 250);
+    }
+
+    #[test]
+    fn rolled_up_balances_fail_instead_of_overflowing() {
+        let accounts = vec![(1, None, i64::MAX), (2, Some(1), 1)];
+
+        match rolled_up_balances(&accounts) {
+            Err(e) => assert_eq!(e.code(), "overflow"),
+            Ok(v) => panic!("Expected an overflow, got {:?}", v),
+        }
+    }
+
+    #[test]
+    fn rolled_up_balances_survive_a_parent_cycle() {
+        let accounts = vec![(1, Some(2), 10), (2, Some(1), 20)];
+
+        let rolled = rolled_up_balances(&accounts).unwrap();
+
+        assert_eq!(rolled.len(), 2);
+    }
+
+    #[test]
+    fn account_tree_nests_children_under_parents() {
+        let accounts = vec![
+            detailed(3, "Groceries", Some(2), 250),
+            detailed(1, "Expenses", None, 0),
+            detailed(2, "Food", Some(1), 100),
+            detailed(4, "Restaurants", Some(2), 50),
+            detailed(5, "Orphan", Some(42), 5),
+        ];
+
+        let tree = AccountNode::tree(accounts).unwrap();
+
+        assert_eq!(tree.len(), 2);
+        assert_eq!(tree[0].name, "Expenses");
+        assert_eq!(tree[0].rolled_up_balance.amount, 400);
+
+        let food = &tree[0].children[0];
+        assert_eq!(food.name, "Food");
+        assert_eq!(food.balance.amount, 100);
+        assert_eq!(food.rolled_up_balance.formatted(), "4.00");
+        assert_eq!(food.children.len(), 2);
+        assert_eq!(food.children[0].name, "Groceries");
+        assert_eq!(tree[1].name, "Orphan");
+    }
+
+    #[test]
+    fn child_has_to_match_parent_type_and_currency() {
+        let food = Account {
+            id: 1,
+            acc_type: AccountType::Expenses,
+            name: String::from("Food"),
+            currency: String::from("GBP"),
+            parent: None,
+            description: None,
+            archived: false,
+        };
+
+        assert!(food.can_parent(AccountType::Expenses, "GBP"));
+        assert!(!food.can_parent(AccountType::Assets, "GBP"));
+        assert!(!food.can_parent(AccountType::Expenses, "EUR"));
+    }
+
+    #[test]
+    fn accounttype_parses_names() {
+        assert_eq!("Assets".parse::<AccountType>(), Ok(AccountType::Assets));
+        assert_eq!(
+            "liabilities".parse::<AccountType>(),
+            Ok(AccountType::Liabilities)
+        );
+        assert_eq!(" GAINS ".parse::<AccountType>(), Ok(AccountType::Gains));
+        assert!("Asset".parse::<AccountType>().is_err());
+    }
+
+    #[test]
+    fn new_account_type_by_name_or_number() {
+        let by_name: NewAccount = serde_json::from_str(
+            r#"{ "acc_type": "Expenses", "name": "Food", "currency": "GBP" }"#,
+        )
+        .unwrap();
+        let by_number: NewAccount =
+            serde_json::from_str(r#"{ "acc_type": 1, "name": "Card", "currency": "GBP" }"#)
+                .unwrap();
+
+        assert_eq!(by_name.acc_type.account_type(), Ok(AccountType::Expenses));
+        assert_eq!(
+            by_number.acc_type.account_type(),
+            Ok(AccountType::Liabilities)
+        );
+        assert!(AccountTypeValue::Number(7).account_type().is_err());
+        assert!(AccountTypeValue::Number(-1).account_type().is_err());
+        assert!(AccountTypeValue::Name(String::from("Assetz"))
+            .account_type()
+            .is_err());
+        assert_eq!(AccountType::from_i32(42), None);
+    }
+
+    fn typed(id: i32, acc_type: AccountType, name: &str, balance: i64) -> DetailedAccount {
+        DetailedAccount {
+            id,
+            acc_type,
+            name: String::from(name),
+            balance: Money::new(balance, "GBP", 2),
+            normal_balance: Money::new(acc_type.normal_balance(balance).unwrap(), "GBP", 2),
+            currency: String::from("GBP"),
+            parent: None,
+            archived: false,
+        }
+    }
+
+    #[test]
+    fn account_query_filters_by_types_and_name() {
+        let accounts = vec![
+            typed(1, AccountType::Assets, "Current", 1000),
+            typed(2, AccountType::Liabilities, "Credit Card", -500),
+            typed(3, AccountType::Equities, "Opening Balance", -500),
+            typed(4, AccountType::Liabilities, "Mortgage", -90000),
+        ];
+        let query = AccountQuery {
+            types: Some(String::from("Liabilities,equities")),
+            q: Some(String::from("c")),
+            sort: None,
+            order: None,
+            archived: None,
+        };
+
+        let result = query.apply(accounts);
+
+        let names: Vec<&str> = result.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, vec!["Credit Card", "Opening Balance"]);
+    }
+
+    #[test]
+    fn account_query_sorts_by_normal_balance() {
+        let accounts = vec![
+            typed(1, AccountType::Assets, "Current", 1000),
+            typed(2, AccountType::Liabilities, "Credit Card", -500),
+            typed(3, AccountType::Liabilities, "Mortgage", -90000),
+        ];
+        let query = AccountQuery {
+            types: None,
+            q: None,
+            sort: Some(AccountSort::Balance),
+            order: Some(SortOrder::Desc),
+            archived: None,
+        };
+
+        let result = query.apply(accounts);
+
+        let ids: Vec<i32> = result.iter().map(|a| a.id).collect();
+        assert_eq!(ids, vec![3, 1, 2]);
+    }
+
+    #[test]
+    fn account_query_rejects_unknown_types() {
+        let query = AccountQuery {
+            types: Some(String::from("Assets,Stuff")),
+            q: None,
+            sort: None,
+            order: None,
+            archived: None,
+        };
+
+        assert!(query.types().is_err());
+    }
 }
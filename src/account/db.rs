@@ -1,151 +1,312 @@
-use crate::account::data::{Account, AccountType, DetailedAccount};
-use rusqlite::{params, Result, NO_PARAMS};
-use std::ops::DerefMut;
+use crate::account::data::{Account, AccountType, DetailedAccount, RegisterEntry};
+use crate::error::{missing, LedgerError, Result};
+use crate::money::{self, Money};
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection};
 
 // Single Account Operations
 
-pub fn get_account(
-    conn: r2d2::PooledConnection<r2d2_sqlite::SqliteConnectionManager>,
-    id: i32,
-) -> Result<Account> {
-    let mut stmt = conn.prepare("SELECT id, type, name, currency FROM Accounts WHERE id = ?1")?;
+pub fn get_account(conn: &Connection, id: i32) -> Result<Account> {
+    let mut stmt = conn.prepare(
+        "SELECT id, type, name, currency, parent, description, archived FROM Accounts WHERE id = ?1",
+    )?;
 
     stmt.query_row(params![id], |row| {
         Ok(Account {
-            id: row.get(0).unwrap(),
-            acc_type: AccountType::from_i32(row.get(1).unwrap()),
-            name: row.get(2).unwrap(),
-            currency: row.get(3).unwrap(),
+            id: row.get(0)?,
+            acc_type: row.get(1)?,
+            name: row.get(2)?,
+            currency: row.get(3)?,
+            parent: row.get(4)?,
+            description: row.get(5)?,
+            archived: row.get(6)?,
         })
     })
+    .map_err(|e| missing(e, "Account", id))
 }
 
 pub fn add_account(
-    mut conn: r2d2::PooledConnection<r2d2_sqlite::SqliteConnectionManager>,
+    conn: &mut Connection,
     acc_type: AccountType,
     name: &str,
     currency: &str,
-) -> Result<()> {
-    let con = conn.deref_mut();
-    let tx = con.transaction()?;
+    parent: Option<i32>,
+    description: Option<&str>,
+) -> Result<i32> {
+    let tx = conn.transaction()?;
 
     tx.execute(
-        "INSERT INTO Accounts (type, name, currency) VALUES (?1, ?2, ?3)",
-        params![acc_type as i32, name, currency],
+        "INSERT INTO Accounts (type, name, currency, parent, description) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![acc_type, name, currency, parent, description],
     )?;
 
-    tx.commit()
+    let id = tx.last_insert_rowid() as i32;
+
+    tx.commit()?;
+
+    Ok(id)
 }
 
-pub fn remove_account(
-    mut conn: r2d2::PooledConnection<r2d2_sqlite::SqliteConnectionManager>,
+pub fn update_account(
+    conn: &mut Connection,
     id: i32,
+    name: &str,
+    description: Option<&str>,
 ) -> Result<()> {
-    let con = conn.deref_mut();
-    let tx = con.transaction()?;
+    let tx = conn.transaction()?;
+
+    let updated = tx.execute(
+        "UPDATE Accounts SET name = ?1, description = ?2 WHERE id = ?3",
+        params![name, description, id],
+    )?;
+
+    if updated == 0 {
+        return Err(LedgerError::not_found("Account", id));
+    }
+
+    tx.commit()?;
+
+    Ok(())
+}
+
+pub fn set_account_archived(conn: &mut Connection, id: i32, archived: bool) -> Result<()> {
+    let tx = conn.transaction()?;
+
+    let updated = tx.execute(
+        "UPDATE Accounts SET archived = ?1 WHERE id = ?2",
+        params![archived, id],
+    )?;
+
+    if updated == 0 {
+        return Err(LedgerError::not_found("Account", id));
+    }
+
+    tx.commit()?;
+
+    Ok(())
+}
+
+// Returns true if any entry, budget entry or child account references the account
+pub fn account_in_use(conn: &Connection, id: i32) -> Result<bool> {
+    let in_use = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM Debits WHERE account = ?1)
+        OR EXISTS(SELECT 1 FROM Credits WHERE account = ?1)
+        OR EXISTS(SELECT 1 FROM BudgetEntries WHERE account = ?1)
+        OR EXISTS(SELECT 1 FROM Accounts WHERE parent = ?1)",
+        params![id],
+        |row| row.get(0),
+    )?;
+
+    Ok(in_use)
+}
+
+pub fn remove_account(conn: &mut Connection, id: i32) -> Result<()> {
+    let tx = conn.transaction()?;
 
     tx.execute("DELETE FROM Accounts WHERE id = ?1", params![id])?;
 
-    tx.commit()
+    tx.commit()?;
+
+    Ok(())
 }
 
 // List Operations
 
-pub fn list_accounts(
-    conn: r2d2::PooledConnection<r2d2_sqlite::SqliteConnectionManager>,
-) -> Result<Vec<Account>> {
-    let mut stmt = conn.prepare("SELECT id, type, name, currency from Accounts")?;
+pub fn list_accounts(conn: &Connection, include_archived: bool) -> Result<Vec<Account>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, type, name, currency, parent, description, archived from Accounts
+        WHERE (?1 OR archived = 0)",
+    )?;
 
     let accounts = stmt
-        .query_map(NO_PARAMS, |row| {
+        .query_map(params![include_archived], |row| {
             Ok(Account {
-                id: row.get(0).unwrap(),
-                acc_type: AccountType::from_i32(row.get(1).unwrap()),
-                name: row.get(2).unwrap(),
-                currency: row.get(3).unwrap(),
+                id: row.get(0)?,
+                acc_type: row.get(1)?,
+                name: row.get(2)?,
+                currency: row.get(3)?,
+                parent: row.get(4)?,
+                description: row.get(5)?,
+                archived: row.get(6)?,
             })
-        })
-        .and_then(|mapped_rows| {
-            Ok(mapped_rows
-                .map(|row| row.unwrap())
-                .collect::<Vec<Account>>())
-        })?;
+        })?
+        .collect::<rusqlite::Result<Vec<Account>>>()?;
 
     Ok(accounts)
 }
 
 pub fn list_accounts_filter_type(
-    conn: r2d2::PooledConnection<r2d2_sqlite::SqliteConnectionManager>,
+    conn: &Connection,
     account_type: AccountType,
 ) -> Result<Vec<DetailedAccount>> {
-    let mut stmt = conn.prepare("SELECT Accounts.id, Accounts.type, Accounts.name, Accounts.currency, 
-    (SELECT ifnull(SUM(balance),0) as \"Debits\" FROM Debits WHERE Debits.account = Accounts.id) - 
-    (SELECT ifnull(SUM(balance),0) as \"Credits\" FROM Credits WHERE Credits.account = Accounts.id) as \"balance\"
-    from Accounts WHERE type = ?1
-    ")?;
+    query_detailed_accounts(conn, Some(account_type), false)
+}
+
+// Every account with its own balance
+pub fn list_detailed_accounts(
+    conn: &Connection,
+    include_archived: bool,
+) -> Result<Vec<DetailedAccount>> {
+    query_detailed_accounts(conn, None, include_archived)
+}
+
+fn query_detailed_accounts(
+    conn: &rusqlite::Connection,
+    account_type: Option<AccountType>,
+    include_archived: bool,
+) -> Result<Vec<DetailedAccount>> {
+    let mut stmt = conn.prepare(
+        "SELECT Accounts.id, Accounts.type, Accounts.name, Accounts.currency, 
+    (SELECT ifnull(SUM(balance),0) as \"Debits\" FROM Debits WHERE Debits.account = Accounts.id),
+    (SELECT ifnull(SUM(balance),0) as \"Credits\" FROM Credits WHERE Credits.account = Accounts.id),
+    Accounts.parent, Accounts.archived, Currency.minor_unit
+    from Accounts INNER JOIN Currency ON Accounts.currency = Currency.code
+    WHERE (?1 IS NULL OR type = ?1) AND (?2 OR archived = 0)
+    ",
+    )?;
+
+    let rows = stmt
+        .query_map(params![account_type, include_archived], |row| {
+            let debits: i64 = row.get(4)?;
+            let credits: i64 = row.get(5)?;
+            let minor_unit: i32 = row.get(8)?;
+
+            Ok((
+                Account {
+                    id: row.get(0)?,
+                    acc_type: row.get(1)?,
+                    name: row.get(2)?,
+                    currency: row.get(3)?,
+                    parent: row.get(6)?,
+                    description: None,
+                    archived: row.get(7)?,
+                },
+                debits,
+                credits,
+                minor_unit,
+            ))
+        })?
+        .collect::<rusqlite::Result<Vec<(Account, i64, i64, i32)>>>()?;
+
+    // The balances are worked out here so that an overflow is an error
+    rows.into_iter()
+        .map(|(account, debits, credits, minor_unit)| {
+            let balance = Money::new(money::sub(debits, credits)?, &account.currency, minor_unit);
 
-    let accounts = stmt
-        .query_map(params![account_type as i32], |row| {
             Ok(DetailedAccount {
-                id: row.get(0).unwrap(),
-                acc_type: AccountType::from_i32(row.get(1).unwrap()),
-                name: row.get(2).unwrap(),
-                currency: row.get(3).unwrap(),
-                balance: row.get(4).unwrap(),
+                id: account.id,
+                acc_type: account.acc_type,
+                name: account.name,
+                normal_balance: account.acc_type.normal_money(&balance)?,
+                balance,
+                currency: account.currency,
+                parent: account.parent,
+                archived: account.archived,
             })
         })
-        .and_then(|mapped_rows| {
-            Ok(mapped_rows
-                .map(|row| row.unwrap())
-                .collect::<Vec<DetailedAccount>>())
-        })?;
+        .collect()
+}
 
-    Ok(accounts)
+// Register operations
+
+// Separates counterpart account names in the register query
+const NAME_SEPARATOR: char = '\u{1f}';
+
+// Every entry of the account in date order. The running balance is calculated
+// over the whole history of the account before the date range and page are applied
+pub fn list_account_entries(
+    conn: &Connection,
+    account: i32,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    limit: u32,
+    offset: u32,
+) -> Result<Vec<RegisterEntry>> {
+    let (acc_type, currency, minor_unit): (AccountType, String, i32) = conn.query_row(
+        "SELECT a.type, a.currency, cu.minor_unit
+        FROM Accounts as a INNER JOIN Currency as cu ON a.currency = cu.code WHERE a.id = ?1",
+        params![account],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )?;
+    let money = |amount: i64| Money::new(amount, &currency, minor_unit);
+
+    let mut stmt = conn.prepare(
+        "WITH entries AS (
+            SELECT id, transaction_id, balance, 1 as entry_type, balance as amount FROM Debits WHERE account = ?1
+            UNION ALL
+            SELECT id, transaction_id, balance, 0 as entry_type, -balance as amount FROM Credits WHERE account = ?1
+        ),
+        register AS (
+            SELECT e.id, e.transaction_id, t.name, t.date, e.entry_type, e.balance,
+            SUM(e.amount) OVER (
+                ORDER BY julianday(t.date), e.transaction_id, e.entry_type DESC, e.id
+                ROWS UNBOUNDED PRECEDING
+            ) as running_balance
+            FROM entries as e INNER JOIN Transactions as t ON e.transaction_id = t.id
+        )
+        SELECT r.id, r.transaction_id, r.name, r.date, r.entry_type, r.balance, r.running_balance,
+        CASE r.entry_type
+            WHEN 1 THEN (SELECT group_concat(a.name, char(31)) FROM Credits as c INNER JOIN Accounts as a ON c.account = a.id WHERE c.transaction_id = r.transaction_id)
+            ELSE (SELECT group_concat(a.name, char(31)) FROM Debits as d INNER JOIN Accounts as a ON d.account = a.id WHERE d.transaction_id = r.transaction_id)
+        END as counterparts
+        FROM register as r
+        WHERE (?2 IS NULL OR julianday(r.date) >= julianday(?2))
+        AND (?3 IS NULL OR julianday(r.date) <= julianday(?3))
+        ORDER BY julianday(r.date), r.transaction_id, r.entry_type DESC, r.id
+        LIMIT ?4 OFFSET ?5",
+    )?;
+
+    let mut entries = stmt
+        .query_map(params![account, from, to, limit, offset], |row| {
+            let running_balance = money(row.get(6)?);
+            let counterparts: Option<String> = row.get(7)?;
+
+            Ok(RegisterEntry {
+                id: row.get(0)?,
+                transaction_id: row.get(1)?,
+                transaction_name: row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+                date: row.get(3)?,
+                entry_type: row.get(4)?,
+                balance: money(row.get(5)?),
+                counterparts: counterparts
+                    .map(|names| names.split(NAME_SEPARATOR).map(String::from).collect())
+                    .unwrap_or_default(),
+                normal_running_balance: running_balance.clone(),
+                running_balance,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<RegisterEntry>>>()?;
+
+    for entry in entries.iter_mut() {
+        entry.normal_running_balance = acc_type.normal_money(&entry.running_balance)?;
+    }
+
+    Ok(entries)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::migrations;
+    use crate::transaction::data::EntryType;
     use r2d2_sqlite::SqliteConnectionManager;
     use rusqlite::params;
 
     #[test]
     fn is_able_to_add_and_select_an_account() {
-        let manager = SqliteConnectionManager::memory();
-        let pool = r2d2::Pool::new(manager).unwrap();
-        let conn = pool.get().unwrap();
-
-        let _ = conn.execute(
-            "CREATE TABLE \"Currency\" (
-            \"code\"	TEXT NOT NULL UNIQUE,
-            \"numeric_code\"	INTEGER NOT NULL UNIQUE,
-            \"minor_unit\"	INTEGER NOT NULL DEFAULT 2,
-            \"name\"	TEXT NOT NULL UNIQUE,
-            PRIMARY KEY(\"code\")
-            )",
-            params![],
-        );
-        let _num = conn.execute(
-            "INSERT INTO Currency (code, numeric_code, minor_unit, name) VALUES ('GBP', '826', '2', 'Pound Sterling');",
-            params![],
+        let pool = migrations::memory_pool();
+        let add_result = add_account(
+            &mut pool.get().unwrap(),
+            AccountType::Assets,
+            "Dank",
+            "GBP",
+            None,
+            None,
         );
 
-        let _ = conn.execute(
-            "CREATE TABLE \"Accounts\" (
-	        \"id\"	INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
-	        \"type\"	INTEGER NOT NULL,
-	        \"name\"	TEXT NOT NULL,
-	        \"currency\"	TEXT NOT NULL,
-	        FOREIGN KEY(\"currency\") REFERENCES \"Currency\"(\"code\")
-            )",
-            params![],
-        );
-
-        let add_result = add_account(conn, AccountType::Assets, "Dank", "GBP");
-
         assert!(add_result.is_ok(), true);
 
-        let account = get_account(pool.get().unwrap(), 1).unwrap();
+        let account = get_account(&pool.get().unwrap(), 1).unwrap();
 
         assert_eq!(account.name, "Dank");
         assert_eq!(account.acc_type, AccountType::Assets);
@@ -154,109 +315,267 @@ mod tests {
 
     #[test]
     fn can_get_all_accounts() {
-        let manager = SqliteConnectionManager::memory();
-        let pool = r2d2::Pool::new(manager).unwrap();
-        let conn = pool.get().unwrap();
-
-        let _ = conn.execute(
-            "CREATE TABLE \"Currency\" (
-            \"code\"	TEXT NOT NULL UNIQUE,
-            \"numeric_code\"	INTEGER NOT NULL UNIQUE,
-            \"minor_unit\"	INTEGER NOT NULL DEFAULT 2,
-            \"name\"	TEXT NOT NULL UNIQUE,
-            PRIMARY KEY(\"code\")
-            )",
-            params![],
+        let pool = migrations::memory_pool();
+        let _ = add_account(
+            &mut pool.get().unwrap(),
+            AccountType::Assets,
+            "Dank",
+            "GBP",
+            None,
+            None,
         );
-        let _num = conn.execute(
-            "INSERT INTO Currency (code, numeric_code, minor_unit, name) VALUES ('GBP', '826', '2', 'Pound Sterling');",
-            params![],
+        let _ = add_account(
+            &mut pool.get().unwrap(),
+            AccountType::Expenses,
+            "Food",
+            "GBP",
+            None,
+            None,
         );
-
-        let _ = conn.execute(
-            "CREATE TABLE \"Accounts\" (
-	        \"id\"	INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
-	        \"type\"	INTEGER NOT NULL,
-	        \"name\"	TEXT NOT NULL,
-	        \"currency\"	TEXT NOT NULL,
-	        FOREIGN KEY(\"currency\") REFERENCES \"Currency\"(\"code\")
-            )",
-            params![],
+        let _ = add_account(
+            &mut pool.get().unwrap(),
+            AccountType::Revenue,
+            "Dab",
+            "GBP",
+            None,
+            None,
         );
 
-        let _ = add_account(conn, AccountType::Assets, "Dank", "GBP");
-        let _ = add_account(pool.get().unwrap(), AccountType::Expenses, "Food", "GBP");
-        let _ = add_account(pool.get().unwrap(), AccountType::Revenue, "Dab", "GBP");
-
-        let accounts: Vec<Account> = list_accounts(pool.get().unwrap()).unwrap();
+        let accounts: Vec<Account> = list_accounts(&pool.get().unwrap(), false).unwrap();
 
         assert_eq!(accounts.len(), 3)
     }
 
     #[test]
     fn can_filter_account_list_by_type() {
-        let manager = SqliteConnectionManager::memory();
-        let pool = r2d2::Pool::new(manager).unwrap();
-        let conn = pool.get().unwrap();
-
-        let _ = conn.execute(
-            "CREATE TABLE \"Currency\" (
-            \"code\"	TEXT NOT NULL UNIQUE,
-            \"numeric_code\"	INTEGER NOT NULL UNIQUE,
-            \"minor_unit\"	INTEGER NOT NULL DEFAULT 2,
-            \"name\"	TEXT NOT NULL UNIQUE,
-            PRIMARY KEY(\"code\")
-            )",
-            params![],
+        let pool = migrations::memory_pool();
+        let _ = add_account(
+            &mut pool.get().unwrap(),
+            AccountType::Assets,
+            "Dank",
+            "GBP",
+            None,
+            None,
         );
-        let _num = conn.execute(
-            "INSERT INTO Currency (code, numeric_code, minor_unit, name) VALUES ('GBP', '826', '2', 'Pound Sterling');",
-            params![],
+        let _ = add_account(
+            &mut pool.get().unwrap(),
+            AccountType::Expenses,
+            "Food",
+            "GBP",
+            None,
+            None,
         );
-
-        let _ = conn.execute(
-            "CREATE TABLE \"Credits\" (
-	        \"id\"	INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
-	        \"account\"	INTEGER NOT NULL,
-	        \"transaction_id\"	INTEGER NOT NULL,
-	        \"balance\"	INTEGER NOT NULL DEFAULT 0 CHECK (typeof(\"balance\") = 'integer'),
-	        FOREIGN KEY(\"account\") REFERENCES \"Accounts\"(\"id\"),
-	        FOREIGN KEY(\"transaction_id\") REFERENCES \"Transactions\"(\"id\") ON DELETE CASCADE
-            )",
-            params![],
+        let _ = add_account(
+            &mut pool.get().unwrap(),
+            AccountType::Revenue,
+            "Dab",
+            "GBP",
+            None,
+            None,
         );
 
-        let _ = conn.execute(
-            "CREATE TABLE \"Debits\" (
-	        \"id\"	INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
-	        \"account\"	INTEGER NOT NULL,
-	        \"transaction_id\"	INTEGER NOT NULL,
-	        \"balance\"	INTEGER NOT NULL DEFAULT 0 CHECK (typeof(\"balance\") = 'integer'),
-	        FOREIGN KEY(\"account\") REFERENCES \"Accounts\"(\"id\"),
-	        FOREIGN KEY(\"transaction_id\") REFERENCES \"Transactions\"(\"id\") ON DELETE CASCADE
-            )",
-            params![],
+        let accounts: Vec<DetailedAccount> =
+            list_accounts_filter_type(&pool.get().unwrap(), AccountType::Assets).unwrap();
+
+        assert_eq!(accounts.len(), 1);
+        assert_eq!(accounts[0].name, "Dank");
+    }
+
+    #[test]
+    fn filtered_accounts_show_normal_balances() {
+        let pool = migrations::memory_pool();
+        let _ = add_account(
+            &mut pool.get().unwrap(),
+            AccountType::Assets,
+            "Current",
+            "GBP",
+            None,
+            None,
         );
+        let _ = add_account(
+            &mut pool.get().unwrap(),
+            AccountType::Liabilities,
+            "Credit Card",
+            "GBP",
+            None,
+            None,
+        );
+
+        // Spending 120.00 on the credit card moves it into the current account
+        pool.get()
+            .unwrap()
+            .execute_batch(
+                "BEGIN;
+                INSERT INTO Transactions (date, name) VALUES ('2020-01-15T10:00:00+00:00', 'Card');
+                INSERT INTO Debits (account, transaction_id, balance) VALUES (1, 1, 12000);
+                INSERT INTO Credits (account, transaction_id, balance) VALUES (2, 1, 12000);
+                COMMIT;",
+            )
+            .unwrap();
+
+        let assets = list_accounts_filter_type(&pool.get().unwrap(), AccountType::Assets).unwrap();
+        let liabilities =
+            list_accounts_filter_type(&pool.get().unwrap(), AccountType::Liabilities).unwrap();
+
+        assert_eq!(assets[0].balance.amount, 12000);
+        assert_eq!(assets[0].normal_balance.amount, 12000);
+        assert_eq!(liabilities[0].balance.amount, -12000);
+        assert_eq!(liabilities[0].normal_balance.formatted(), "120.00");
+    }
+
+    fn create_register(pool: &r2d2::Pool<SqliteConnectionManager>) {
+        // Inserted out of date order on purpose
+        pool.get()
+            .unwrap()
+            .execute_batch(
+                "INSERT INTO Accounts (type, name, currency) VALUES
+                (0, 'Current', 'GBP'), (4, 'Groceries', 'GBP'), (4, 'Household', 'GBP'), (3, 'Salary', 'GBP');
+                BEGIN;
+                INSERT INTO Transactions (date, name) VALUES
+                ('2020-01-15T10:00:00+00:00', 'Weekly shop'),
+                ('2020-01-01T09:00:00+00:00', 'Salary'),
+                ('2020-02-01T09:00:00+00:00', 'Salary');
+                INSERT INTO Debits (account, transaction_id, balance) VALUES
+                (2, 1, 3000), (3, 1, 1000), (1, 2, 100000), (1, 3, 100000);
+                INSERT INTO Credits (account, transaction_id, balance) VALUES
+                (1, 1, 4000), (4, 2, 100000), (4, 3, 100000);
+                COMMIT;",
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn register_lists_entries_in_date_order_with_running_balance() {
+        let pool = migrations::memory_pool();
+        create_register(&pool);
+
+        let entries = list_account_entries(&pool.get().unwrap(), 1, None, None, 50, 0).unwrap();
+
+        let running: Vec<i64> = entries.iter().map(|e| e.running_balance.amount).collect();
+        assert_eq!(running, vec![100000, 96000, 196000]);
+
+        assert_eq!(entries[1].transaction_name, "Weekly shop");
+        assert_eq!(entries[1].entry_type, EntryType::Credit);
+        assert_eq!(entries[1].counterparts.len(), 2);
+        assert!(entries[1].counterparts.contains(&String::from("Groceries")));
+        assert!(entries[1].counterparts.contains(&String::from("Household")));
+        assert_eq!(entries[0].counterparts, vec![String::from("Salary")]);
+    }
+
+    #[test]
+    fn register_filters_and_pages_without_resetting_running_balance() {
+        let pool = migrations::memory_pool();
+        create_register(&pool);
+
+        let from = DateTime::parse_from_rfc3339("2020-01-10T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+
+        let page = list_account_entries(&pool.get().unwrap(), 1, Some(from), None, 1, 1).unwrap();
+
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].transaction_id, 3);
+        assert_eq!(page[0].running_balance.amount, 196000);
+
+        let salary = list_account_entries(&pool.get().unwrap(), 4, None, None, 50, 0).unwrap();
+        assert_eq!(salary[1].running_balance.amount, -200000);
+        assert_eq!(salary[1].normal_running_balance.amount, 200000);
+    }
 
-        let _ = conn.execute(
-            "CREATE TABLE \"Accounts\" (
-	        \"id\"	INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
-	        \"type\"	INTEGER NOT NULL,
-	        \"name\"	TEXT NOT NULL,
-	        \"currency\"	TEXT NOT NULL,
-	        FOREIGN KEY(\"currency\") REFERENCES \"Currency\"(\"code\")
-            )",
-            params![],
+    #[test]
+    fn child_accounts_keep_their_parent() {
+        let pool = migrations::memory_pool();
+        create_register(&pool);
+
+        let _ = add_account(
+            &mut pool.get().unwrap(),
+            AccountType::Expenses,
+            "Fruit",
+            "GBP",
+            Some(2),
+            None,
         );
 
-        let _ = add_account(conn, AccountType::Assets, "Dank", "GBP");
-        let _ = add_account(pool.get().unwrap(), AccountType::Expenses, "Food", "GBP");
-        let _ = add_account(pool.get().unwrap(), AccountType::Revenue, "Dab", "GBP");
+        let fruit = get_account(&pool.get().unwrap(), 5).unwrap();
+        assert_eq!(fruit.parent, Some(2));
 
-        let accounts: Vec<DetailedAccount> =
-            list_accounts_filter_type(pool.get().unwrap(), AccountType::Assets).unwrap();
+        let accounts = list_detailed_accounts(&pool.get().unwrap(), true).unwrap();
+        assert_eq!(accounts.len(), 5);
+        assert_eq!(accounts.iter().filter(|a| a.parent == Some(2)).count(), 1);
+    }
 
-        assert_eq!(accounts.len(), 1);
-        assert_eq!(accounts[0].name, "Dank");
+    #[test]
+    fn archived_accounts_are_hidden_but_kept() {
+        let pool = migrations::memory_pool();
+        create_register(&pool);
+
+        let result = set_account_archived(&mut pool.get().unwrap(), 3, true);
+        assert!(result.is_ok());
+
+        let open = list_accounts(&pool.get().unwrap(), false).unwrap();
+        let all = list_accounts(&pool.get().unwrap(), true).unwrap();
+        let expenses =
+            list_accounts_filter_type(&pool.get().unwrap(), AccountType::Expenses).unwrap();
+
+        assert_eq!(open.len(), 3);
+        assert_eq!(all.len(), 4);
+        assert_eq!(expenses.len(), 1);
+        assert!(get_account(&pool.get().unwrap(), 3).unwrap().archived);
+    }
+
+    #[test]
+    fn update_account_renames_and_describes() {
+        let pool = migrations::memory_pool();
+        create_register(&pool);
+
+        let result = update_account(&mut pool.get().unwrap(), 2, "Food", Some("Supermarkets"));
+        let missing = update_account(&mut pool.get().unwrap(), 42, "Ghost", None);
+
+        assert!(result.is_ok());
+        assert_eq!(missing.unwrap_err().code(), "not_found");
+
+        let account = get_account(&pool.get().unwrap(), 2).unwrap();
+        assert_eq!(account.name, "Food");
+        assert_eq!(account.description, Some(String::from("Supermarkets")));
+    }
+
+    #[test]
+    fn accounts_with_entries_are_in_use() {
+        let pool = migrations::memory_pool();
+        create_register(&pool);
+
+        pool.get()
+            .unwrap()
+            .execute_batch(
+                "INSERT INTO Accounts (type, name, currency) VALUES (4, 'Unused', 'GBP'), (4, 'Budgeted', 'GBP');
+                INSERT INTO Budgets (name, open, close) VALUES ('January', '2020-01-01T00:00:00+00:00', '2020-01-31T00:00:00+00:00');
+                INSERT INTO BudgetEntries (account, budget, balance) VALUES (6, 1, 100);",
+            )
+            .unwrap();
+
+        assert!(account_in_use(&pool.get().unwrap(), 1).unwrap());
+        assert!(account_in_use(&pool.get().unwrap(), 4).unwrap());
+        assert!(!account_in_use(&pool.get().unwrap(), 5).unwrap());
+        assert!(account_in_use(&pool.get().unwrap(), 6).unwrap());
+    }
+
+    #[test]
+    fn unknown_stored_account_type_is_an_error() {
+        let pool = migrations::memory_pool();
+        create_register(&pool);
+
+        pool.get()
+            .unwrap()
+            .execute("UPDATE Accounts SET type = 9 WHERE id = 1", params![])
+            .unwrap();
+
+        let account = get_account(&pool.get().unwrap(), 1);
+        let accounts = list_accounts(&pool.get().unwrap(), true);
+
+        match account {
+            Err(LedgerError::Integrity(_)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        assert!(accounts.is_err());
     }
 }
@@ -1,85 +1,127 @@
-use actix_web::{web, Error, HttpResponse};
-use r2d2::Pool;
-use r2d2_sqlite::SqliteConnectionManager;
+use actix_web::{web, HttpResponse};
 
 pub mod data;
 pub mod db;
 
 use crate::datastruct;
+use crate::error::{LedgerError, Result};
+use crate::Ledger;
 
 pub async fn get_account(
     params: web::Path<datastruct::IdRequest>,
-    pool: web::Data<Pool<SqliteConnectionManager>>,
-) -> Result<HttpResponse, Error> {
-    let conn = pool.get().unwrap();
-    let result = db::get_account(conn, params.id);
-
-    match result {
-        Ok(v) => Ok(HttpResponse::Ok().json(v)),
-        Err(_e) => Ok(HttpResponse::InternalServerError().finish()),
-    }
+    ledger: web::Data<Ledger>,
+) -> Result<HttpResponse> {
+    let account = ledger.account(params.id)?;
+
+    Ok(HttpResponse::Ok().json(account))
 }
 
 pub async fn create_account(
     account: web::Json<data::NewAccount>,
-    pool: web::Data<Pool<SqliteConnectionManager>>,
-) -> Result<HttpResponse, Error> {
-    let account_type = data::AccountType::from_i32(account.acc_type);
-    let result = db::add_account(
-        pool.get().unwrap(),
-        account_type,
-        &account.name,
-        &account.currency,
-    );
-
-    match result {
-        Ok(v) => Ok(HttpResponse::Ok().json(v)),
-        Err(_e) => Ok(HttpResponse::InternalServerError().finish()),
-    }
+    ledger: web::Data<Ledger>,
+) -> Result<HttpResponse> {
+    ledger.create_account(&account)?;
+
+    Ok(HttpResponse::Ok().json(()))
+}
+
+pub async fn update_account(
+    params: web::Path<datastruct::IdRequest>,
+    account: web::Json<data::UpdateAccount>,
+    ledger: web::Data<Ledger>,
+) -> Result<HttpResponse> {
+    let result = ledger.update_account(params.id, &account)?;
+
+    Ok(HttpResponse::Ok().json(result))
+}
+
+// Archived accounts are hidden from listings and can't receive new entries
+pub async fn archive_account(
+    params: web::Path<datastruct::IdRequest>,
+    ledger: web::Data<Ledger>,
+) -> Result<HttpResponse> {
+    ledger.archive_account(params.id)?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+pub async fn reopen_account(
+    params: web::Path<datastruct::IdRequest>,
+    ledger: web::Data<Ledger>,
+) -> Result<HttpResponse> {
+    ledger.reopen_account(params.id)?;
+
+    Ok(HttpResponse::Ok().finish())
 }
 
+// Only accounts that nothing references can be deleted.
+// Accounts with history should be archived instead
 pub async fn delete_account(
     params: web::Path<datastruct::IdRequest>,
-    pool: web::Data<Pool<SqliteConnectionManager>>,
-) -> Result<HttpResponse, Error> {
-    let result = db::remove_account(pool.get().unwrap(), params.id);
+    ledger: web::Data<Ledger>,
+) -> Result<HttpResponse> {
+    ledger.delete_account(params.id)?;
 
-    match result {
-        Ok(_v) => Ok(HttpResponse::Ok().finish()),
-        Err(_e) => Ok(HttpResponse::InternalServerError().finish()),
-    }
+    Ok(HttpResponse::Ok().finish())
 }
 
 pub async fn list_accounts(
-    pool: web::Data<Pool<SqliteConnectionManager>>,
-) -> Result<HttpResponse, Error> {
-    let conn = pool.get().unwrap();
-    let result = db::list_accounts(conn);
-
-    match result {
-        Ok(v) => Ok(HttpResponse::Ok().json(v)),
-        Err(_e) => Ok(HttpResponse::InternalServerError().finish()),
-    }
+    query: web::Query<data::ArchivedQuery>,
+    ledger: web::Data<Ledger>,
+) -> Result<HttpResponse> {
+    let result = ledger.accounts(query.include_archived())?;
+
+    Ok(HttpResponse::Ok().json(result))
 }
 
-pub async fn list_asset_accounts(
-    pool: web::Data<Pool<SqliteConnectionManager>>,
-) -> Result<HttpResponse, Error> {
-    let result = db::list_accounts_filter_type(pool.get().unwrap(), data::AccountType::Assets);
+// The chart of accounts with rolled up balances
+pub async fn list_account_tree(ledger: web::Data<Ledger>) -> Result<HttpResponse> {
+    let result = ledger.account_tree()?;
 
-    match result {
-        Ok(v) => Ok(HttpResponse::Ok().json(v)),
-        Err(_e) => Ok(HttpResponse::InternalServerError().finish()),
-    }
+    Ok(HttpResponse::Ok().json(result))
 }
 
-pub async fn list_expense_accounts(
-    pool: web::Data<Pool<SqliteConnectionManager>>,
-) -> Result<HttpResponse, Error> {
-    let result = db::list_accounts_filter_type(pool.get().unwrap(), data::AccountType::Expenses);
+// Accounts with balances, filtered by type and name
+pub async fn search_accounts(
+    query: web::Query<data::AccountQuery>,
+    ledger: web::Data<Ledger>,
+) -> Result<HttpResponse> {
+    let result = ledger.search_accounts(&query)?;
 
-    match result {
-        Ok(v) => Ok(HttpResponse::Ok().json(v)),
-        Err(_e) => Ok(HttpResponse::InternalServerError().finish()),
+    Ok(HttpResponse::Ok().json(result))
+}
+
+pub async fn list_asset_accounts(ledger: web::Data<Ledger>) -> Result<HttpResponse> {
+    let result = ledger.accounts_of_type(data::AccountType::Assets)?;
+
+    Ok(HttpResponse::Ok().json(result))
+}
+
+pub async fn list_expense_accounts(ledger: web::Data<Ledger>) -> Result<HttpResponse> {
+    let result = ledger.accounts_of_type(data::AccountType::Expenses)?;
+
+    Ok(HttpResponse::Ok().json(result))
+}
+
+// Every entry of an account with a running balance
+pub async fn list_account_entries(
+    params: web::Path<datastruct::IdRequest>,
+    query: web::Query<data::RegisterQuery>,
+    ledger: web::Data<Ledger>,
+) -> Result<HttpResponse> {
+    if !query.valid_dates() {
+        return Err(LedgerError::Validation(String::from(
+            "from and to have to be RFC 3339 timestamps",
+        )));
     }
+
+    let result = ledger.register(
+        params.id,
+        query.from(),
+        query.to(),
+        query.limit(),
+        query.offset(),
+    )?;
+
+    Ok(HttpResponse::Ok().json(result))
 }
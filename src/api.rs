@@ -1,49 +1,56 @@
-use actix_web::{web, Error, HttpResponse};
-use serde_json::json;
-
-use r2d2::Pool;
-use r2d2_sqlite::SqliteConnectionManager;
+use actix_web::{web, HttpResponse};
 
+use crate::account::data::IntervalQuery;
 use crate::datastruct;
-use crate::db;
+use crate::error::{LedgerError, Result};
+use crate::Ledger;
 
 pub async fn get_account_balance(
     params: web::Path<datastruct::IdRequest>,
-    pool: web::Data<Pool<SqliteConnectionManager>>,
-) -> Result<HttpResponse, Error> {
-    let result = db::current_balance(pool.get().unwrap(), params.id);
-
-    match result {
-        Ok(v) => Ok(HttpResponse::Ok().json(v)),
-        Err(_e) => Ok(HttpResponse::InternalServerError().finish()),
+    query: web::Query<datastruct::AsOfQuery>,
+    ledger: web::Data<Ledger>,
+) -> Result<HttpResponse> {
+    if !query.valid_date() {
+        return Err(LedgerError::Validation(String::from(
+            "date has to be an RFC 3339 timestamp",
+        )));
     }
-}
 
-pub async fn list_currencies(
-    pool: web::Data<Pool<SqliteConnectionManager>>,
-) -> Result<HttpResponse, Error> {
-    let conn = pool.get().unwrap();
-    let result = db::list_currencies(conn);
+    // Without a date every transaction counts, including future dated ones
+    let date = if query.is_empty() {
+        None
+    } else {
+        Some(query.date())
+    };
 
-    match result {
-        Ok(v) => Ok(HttpResponse::Ok().json(v)),
-        Err(_e) => Ok(HttpResponse::InternalServerError().finish()),
-    }
+    let result = ledger.balance(params.id, date)?;
+
+    Ok(HttpResponse::Ok().json(result))
 }
 
-pub async fn check_ledger_integrity(
-    pool: web::Data<Pool<SqliteConnectionManager>>,
-) -> Result<HttpResponse, Error> {
-    let conn = pool.get().unwrap();
-    let result = db::check_integrity(conn);
-
-    match result {
-        Ok(v) => {
-            let result = json!({
-                "integrity": v,
-            });
-            Ok(HttpResponse::Ok().json(result))
-        }
-        Err(_e) => Ok(HttpResponse::InternalServerError().finish()),
+// Closing balances of an account for every period in the range
+pub async fn get_account_balance_history(
+    params: web::Path<datastruct::IdRequest>,
+    range: web::Query<datastruct::RangeQuery>,
+    interval: web::Query<IntervalQuery>,
+    ledger: web::Data<Ledger>,
+) -> Result<HttpResponse> {
+    if !range.valid_range() {
+        return Err(LedgerError::Validation(String::from(
+            "from and to have to be RFC 3339 timestamps with from before to",
+        )));
     }
+
+    let from = range.from().unwrap();
+    let to = range.to();
+
+    let result = ledger.balance_history(params.id, interval.interval(), from, to)?;
+
+    Ok(HttpResponse::Ok().json(result))
+}
+
+pub async fn check_ledger_integrity(ledger: web::Data<Ledger>) -> Result<HttpResponse> {
+    let report = ledger.check_integrity()?;
+
+    Ok(HttpResponse::Ok().json(report))
 }
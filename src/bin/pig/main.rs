@@ -0,0 +1,604 @@
+// pig works on the ledger database directly, without a running server
+
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+use structopt::StructOpt;
+
+use ledger::account::data::AccountQuery;
+use ledger::datastruct::{self, IntegrityReport};
+use ledger::error::{LedgerError, Result};
+use ledger::money::{Amount, Money};
+use ledger::report::data::StatementSection;
+use ledger::transaction::data::{EntryType, NewEntry, NewTransaction};
+use ledger::Ledger;
+
+mod table;
+
+use table::Table;
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "pig",
+    about = "Bookkeeping for the PIG ledger from the terminal"
+)]
+struct Pig {
+    /// Path of the sqlite database
+    #[structopt(
+        long,
+        env = "LEDGER_DATABASE",
+        default_value = "ledger.db",
+        parse(from_os_str)
+    )]
+    database: PathBuf,
+    /// Print JSON instead of tables
+    #[structopt(long, global = true)]
+    json: bool,
+    #[structopt(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// List accounts with their balances
+    Accounts {
+        /// Comma separated account types, e.g. assets,expenses
+        #[structopt(long = "type")]
+        types: Option<String>,
+        /// Only accounts with this in their name
+        #[structopt(short, long)]
+        search: Option<String>,
+        /// Include archived accounts
+        #[structopt(long)]
+        archived: bool,
+    },
+    /// Add a transaction
+    Add {
+        name: String,
+        /// Debit leg as ACCOUNT=AMOUNT in minor units, e.g. 4=1250
+        #[structopt(short, long = "debit", required = true, number_of_values = 1)]
+        debits: Vec<Leg>,
+        /// Credit leg as ACCOUNT=AMOUNT in minor units, e.g. 1=1250
+        #[structopt(short, long = "credit", required = true, number_of_values = 1)]
+        credits: Vec<Leg>,
+        /// YYYY-MM-DD or an RFC 3339 timestamp. Defaults to now
+        #[structopt(long, parse(try_from_str = start_of_day))]
+        date: Option<DateTime<Utc>>,
+    },
+    /// Show the entries of an account with a running balance
+    Register {
+        account: i32,
+        /// YYYY-MM-DD or an RFC 3339 timestamp
+        #[structopt(long, parse(try_from_str = start_of_day))]
+        from: Option<DateTime<Utc>>,
+        /// YYYY-MM-DD (inclusive) or an RFC 3339 timestamp
+        #[structopt(long, parse(try_from_str = end_of_day))]
+        to: Option<DateTime<Utc>>,
+        #[structopt(long, default_value = "50")]
+        limit: u32,
+        #[structopt(long, default_value = "0")]
+        offset: u32,
+    },
+    /// Check that every transaction is balanced. Exits with 1 if it is not
+    Integrity,
+    /// Print a financial report
+    Report(ReportCommand),
+    /// Show a budget with the spending against it. Defaults to the current one
+    Budget { id: Option<i32> },
+}
+
+#[derive(Debug, StructOpt)]
+enum ReportCommand {
+    /// Debits, credits and balance of every account
+    TrialBalance {
+        /// YYYY-MM-DD (inclusive) or an RFC 3339 timestamp. Defaults to now
+        #[structopt(long, parse(try_from_str = end_of_day))]
+        date: Option<DateTime<Utc>>,
+    },
+    /// Assets, liabilities and equity
+    BalanceSheet {
+        /// YYYY-MM-DD (inclusive) or an RFC 3339 timestamp. Defaults to now
+        #[structopt(long, parse(try_from_str = end_of_day))]
+        date: Option<DateTime<Utc>>,
+    },
+    /// Assets and liabilities converted into one currency
+    NetWorth {
+        /// Currency to report in, e.g. GBP
+        #[structopt(long)]
+        currency: String,
+        /// YYYY-MM-DD (inclusive) or an RFC 3339 timestamp. Defaults to now
+        #[structopt(long, parse(try_from_str = end_of_day))]
+        date: Option<DateTime<Utc>>,
+    },
+    /// Revenue, gains, expenses and losses over a period
+    IncomeStatement {
+        /// YYYY-MM-DD or an RFC 3339 timestamp
+        #[structopt(long, parse(try_from_str = start_of_day))]
+        from: DateTime<Utc>,
+        /// YYYY-MM-DD (inclusive) or an RFC 3339 timestamp. Defaults to now
+        #[structopt(long, parse(try_from_str = end_of_day))]
+        to: Option<DateTime<Utc>>,
+    },
+}
+
+// A single leg of a transaction given as ACCOUNT=AMOUNT. The amount is in
+// minor units, e.g. 1250, or a decimal in the account's currency, e.g. 12.50
+#[derive(Debug)]
+struct Leg(NewEntry);
+
+impl FromStr for Leg {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Leg, String> {
+        let mut parts = value.splitn(2, '=');
+        let account = parts.next().and_then(|v| v.trim().parse().ok());
+        let balance = parts.next().map(Amount::from_str);
+
+        match (account, balance) {
+            (Some(account), Some(Ok(balance))) => Ok(Leg(NewEntry { account, balance })),
+            (Some(_), Some(Err(e))) => Err(e),
+            _ => Err(format!("Expected ACCOUNT=AMOUNT, got {}", value)),
+        }
+    }
+}
+
+fn parse_day(value: &str, end: bool) -> std::result::Result<DateTime<Utc>, String> {
+    if let Ok(day) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        let time = if end {
+            day.and_hms(23, 59, 59)
+        } else {
+            day.and_hms(0, 0, 0)
+        };
+        return Ok(Utc.from_utc_datetime(&time));
+    }
+
+    datastruct::parse_date(value).ok_or_else(|| {
+        format!(
+            "Expected YYYY-MM-DD or an RFC 3339 timestamp, got {}",
+            value
+        )
+    })
+}
+
+fn start_of_day(value: &str) -> std::result::Result<DateTime<Utc>, String> {
+    parse_day(value, false)
+}
+
+fn end_of_day(value: &str) -> std::result::Result<DateTime<Utc>, String> {
+    parse_day(value, true)
+}
+
+fn print_json<T: Serialize>(value: &T) -> Result<()> {
+    let json = serde_json::to_string_pretty(value)
+        .map_err(|e| LedgerError::Integrity(format!("Can't serialize output: {}", e)))?;
+    println!("{}", json);
+
+    Ok(())
+}
+
+fn day(date: DateTime<Utc>) -> String {
+    date.format("%Y-%m-%d").to_string()
+}
+
+fn accounts(ledger: &Ledger, json: bool, query: AccountQuery) -> Result<()> {
+    let result = ledger.search_accounts(&query)?;
+
+    if json {
+        return print_json(&result);
+    }
+
+    let mut table =
+        Table::new(&["Id", "Type", "Name", "Currency", "Parent", "Balance"]).align_right(&[0, 5]);
+    for acc in result {
+        table.row(vec![
+            acc.id.to_string(),
+            acc.acc_type.name().to_string(),
+            if acc.archived {
+                format!("{} (archived)", acc.name)
+            } else {
+                acc.name
+            },
+            acc.currency,
+            acc.parent.map(|p| p.to_string()).unwrap_or_default(),
+            acc.normal_balance.formatted(),
+        ]);
+    }
+    print!("{}", table);
+
+    Ok(())
+}
+
+// Goes through the same checks as POST /transactions
+fn add(ledger: &Ledger, json: bool, new: NewTransaction) -> Result<()> {
+    let created = ledger.create_transaction(&new)?;
+
+    if json {
+        return print_json(&created);
+    }
+
+    println!("Added transaction {} {}", created.id(), created.name());
+
+    Ok(())
+}
+
+fn register(
+    ledger: &Ledger,
+    json: bool,
+    id: i32,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    limit: u32,
+    offset: u32,
+) -> Result<()> {
+    let account = ledger.account(id)?;
+    let register = ledger.register(id, from, to, limit, offset)?;
+
+    if json {
+        return print_json(&register);
+    }
+
+    println!("{} ({})", account.name, account.currency);
+
+    let mut table = Table::new(&[
+        "Date",
+        "Transaction",
+        "Name",
+        "Counterparts",
+        "Debit",
+        "Credit",
+        "Balance",
+    ])
+    .align_right(&[1, 4, 5, 6]);
+    for entry in register.entries {
+        let (debit, credit) = match entry.entry_type {
+            EntryType::Debit => (entry.balance.formatted(), String::new()),
+            EntryType::Credit => (String::new(), entry.balance.formatted()),
+        };
+
+        table.row(vec![
+            day(entry.date),
+            entry.transaction_id.to_string(),
+            entry.transaction_name,
+            entry.counterparts.join(", "),
+            debit,
+            credit,
+            entry.normal_running_balance.formatted(),
+        ]);
+    }
+    print!("{}", table);
+
+    Ok(())
+}
+
+fn integrity(ledger: &Ledger, json: bool) -> Result<IntegrityReport> {
+    let report = ledger.check_integrity()?;
+
+    if json {
+        print_json(&report)?;
+        return Ok(report);
+    }
+
+    println!(
+        "Checked {} transactions: {} findings",
+        report.transactions,
+        report.findings.len()
+    );
+
+    if !report.findings.is_empty() {
+        let mut table = Table::new(&["Kind", "Transaction", "Entry", "Account", "Message"])
+            .align_right(&[1, 2, 3]);
+        for finding in &report.findings {
+            let kind = serde_json::to_value(finding.kind)
+                .ok()
+                .and_then(|v| v.as_str().map(String::from))
+                .unwrap_or_default();
+            let optional = |v: Option<i32>| v.map(|v| v.to_string()).unwrap_or_default();
+
+            table.row(vec![
+                kind,
+                optional(finding.transaction),
+                optional(finding.entry),
+                optional(finding.account),
+                finding.message.clone(),
+            ]);
+        }
+        print!("{}", table);
+    }
+
+    Ok(report)
+}
+
+// Adds the lines of a statement section followed by its subtotals
+fn section_rows(table: &mut Table, title: &str, section: &StatementSection) {
+    for line in &section.lines {
+        table.row(vec![
+            title.to_string(),
+            line.name.clone(),
+            line.currency.clone(),
+            line.balance.formatted(),
+        ]);
+    }
+    total_rows(table, &format!("Total {}", title), &section.subtotals);
+}
+
+fn total_rows(table: &mut Table, title: &str, amounts: &[Money]) {
+    for amount in amounts {
+        table.row(vec![
+            title.to_string(),
+            String::new(),
+            amount.currency.clone(),
+            amount.formatted(),
+        ]);
+    }
+}
+
+fn statement_table() -> Table {
+    Table::new(&["Section", "Account", "Currency", "Balance"]).align_right(&[3])
+}
+
+fn report(ledger: &Ledger, json: bool, command: ReportCommand) -> Result<()> {
+    match command {
+        ReportCommand::TrialBalance { date } => {
+            let date = date.unwrap_or_else(Utc::now);
+            let report = ledger.trial_balance(date)?;
+
+            if json {
+                return print_json(&report);
+            }
+
+            println!("Trial balance as of {}", day(date));
+            let mut table = Table::new(&[
+                "Type", "Id", "Account", "Currency", "Debits", "Credits", "Balance",
+            ])
+            .align_right(&[1, 4, 5, 6]);
+            for group in &report.groups {
+                for line in &group.accounts {
+                    table.row(vec![
+                        group.acc_type.name().to_string(),
+                        line.id.to_string(),
+                        line.name.clone(),
+                        line.currency.clone(),
+                        line.debits.formatted(),
+                        line.credits.formatted(),
+                        line.balance.formatted(),
+                    ]);
+                }
+            }
+            for total in &report.totals {
+                table.row(vec![
+                    String::from("Total"),
+                    String::new(),
+                    String::new(),
+                    total.currency.clone(),
+                    total.debits.formatted(),
+                    total.credits.formatted(),
+                    total.debits.checked_sub(total.credits.amount)?.formatted(),
+                ]);
+            }
+            print!("{}", table);
+            println!("Balanced: {}", if report.balanced { "yes" } else { "no" });
+        }
+        ReportCommand::BalanceSheet { date } => {
+            let date = date.unwrap_or_else(Utc::now);
+            let report = ledger.balance_sheet(date)?;
+
+            if json {
+                return print_json(&report);
+            }
+
+            println!("Balance sheet as of {}", day(date));
+            let mut table = statement_table();
+            section_rows(&mut table, "Assets", &report.assets);
+            section_rows(&mut table, "Liabilities", &report.liabilities);
+            section_rows(&mut table, "Equity", &report.equity);
+            total_rows(&mut table, "Retained earnings", &report.retained_earnings);
+            total_rows(
+                &mut table,
+                "Liabilities and equity",
+                &report.liabilities_and_equity,
+            );
+            print!("{}", table);
+            println!("Balanced: {}", if report.balanced { "yes" } else { "no" });
+        }
+        ReportCommand::NetWorth { currency, date } => {
+            let date = date.unwrap_or_else(Utc::now);
+            let report = ledger.net_worth(&currency, date)?;
+
+            if json {
+                return print_json(&report);
+            }
+
+            println!("Net worth in {} as of {}", report.currency, day(date));
+            let optional = |v: &Option<Money>| {
+                v.as_ref()
+                    .map(|m| m.formatted())
+                    .unwrap_or_else(|| String::from("no rate"))
+            };
+
+            let mut table = Table::new(&["Type", "Account", "Currency", "Balance", "Converted"])
+                .align_right(&[3, 4]);
+            for line in &report.accounts {
+                table.row(vec![
+                    line.acc_type.name().to_string(),
+                    line.name.clone(),
+                    line.balance.currency.clone(),
+                    line.balance.formatted(),
+                    optional(&line.converted),
+                ]);
+            }
+            for subtotal in &report.subtotals {
+                table.row(vec![
+                    String::from("Subtotal"),
+                    String::new(),
+                    subtotal.subtotal.currency.clone(),
+                    subtotal.subtotal.formatted(),
+                    optional(&subtotal.converted),
+                ]);
+            }
+            table.row(vec![
+                String::from("Total"),
+                String::new(),
+                report.currency.clone(),
+                String::new(),
+                report.total.formatted(),
+            ]);
+            print!("{}", table);
+
+            if !report.complete {
+                println!("Accounts without an exchange rate are left out of the total");
+            }
+        }
+        ReportCommand::IncomeStatement { from, to } => {
+            let to = to.unwrap_or_else(Utc::now);
+            let report = ledger.income_statement(from, to)?;
+
+            if json {
+                return print_json(&report);
+            }
+
+            println!("Income statement {} - {}", day(from), day(to));
+            let mut table = statement_table();
+            section_rows(&mut table, "Revenue", &report.revenue);
+            section_rows(&mut table, "Gains", &report.gains);
+            section_rows(&mut table, "Expenses", &report.expenses);
+            section_rows(&mut table, "Losses", &report.losses);
+            total_rows(&mut table, "Net income", &report.net_income);
+            print!("{}", table);
+        }
+    }
+
+    Ok(())
+}
+
+fn show_budget(ledger: &Ledger, json: bool, id: Option<i32>) -> Result<()> {
+    let budget = match id {
+        Some(id) => ledger.budget(id)?,
+        None => ledger.current_budget()?,
+    };
+    let entries = ledger.budget_entries(budget.id)?;
+
+    if json {
+        return print_json(&serde_json::json!({
+            "budget": budget,
+            "entries": entries,
+        }));
+    }
+
+    let names: HashMap<i32, String> = ledger
+        .accounts(true)?
+        .into_iter()
+        .map(|a| (a.id, a.name))
+        .collect();
+
+    println!(
+        "{} {} - {}",
+        budget.name,
+        day(budget.open),
+        day(budget.close)
+    );
+
+    let mut table =
+        Table::new(&["Account", "Budgeted", "Spent", "Remaining"]).align_right(&[1, 2, 3]);
+    for entry in entries {
+        table.row(vec![
+            names.get(&entry.account).cloned().unwrap_or_default(),
+            entry.balance.formatted(),
+            entry.spent.formatted(),
+            entry.balance.checked_sub(entry.spent.amount)?.formatted(),
+        ]);
+    }
+    print!("{}", table);
+
+    Ok(())
+}
+
+fn run(pig: Pig) -> Result<bool> {
+    let ledger = Ledger::open(&pig.database)?;
+
+    match pig.command {
+        Command::Accounts {
+            types,
+            search,
+            archived,
+        } => accounts(
+            &ledger,
+            pig.json,
+            AccountQuery::new(types, search, archived),
+        )?,
+        Command::Add {
+            name,
+            debits,
+            credits,
+            date,
+        } => add(
+            &ledger,
+            pig.json,
+            NewTransaction {
+                name,
+                date: date.map(|d| d.to_rfc3339()),
+                debits: debits.into_iter().map(|l| l.0).collect(),
+                credits: credits.into_iter().map(|l| l.0).collect(),
+            },
+        )?,
+        Command::Register {
+            account,
+            from,
+            to,
+            limit,
+            offset,
+        } => register(&ledger, pig.json, account, from, to, limit, offset)?,
+        Command::Integrity => return Ok(integrity(&ledger, pig.json)?.integrity),
+        Command::Report(command) => report(&ledger, pig.json, command)?,
+        Command::Budget { id } => show_budget(&ledger, pig.json, id)?,
+    }
+
+    Ok(true)
+}
+
+fn main() {
+    match run(Pig::from_args()) {
+        Ok(true) => {}
+        Ok(false) => std::process::exit(1),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legs_are_account_and_amount() {
+        let leg: Leg = "4=1250".parse().unwrap();
+
+        assert_eq!(leg.0.account, 4);
+        assert_eq!(leg.0.balance, Amount::Minor(1250));
+        assert!("4:1250".parse::<Leg>().is_err());
+        assert!("4=12,50".parse::<Leg>().is_err());
+
+        let decimal: Leg = "4=12.50".parse().unwrap();
+        assert_eq!(decimal.0.balance, Amount::Decimal(String::from("12.50")));
+    }
+
+    #[test]
+    fn days_cover_the_whole_day() {
+        assert_eq!(
+            start_of_day("2020-02-01").unwrap(),
+            Utc.ymd(2020, 2, 1).and_hms(0, 0, 0)
+        );
+        assert_eq!(
+            end_of_day("2020-02-01").unwrap(),
+            Utc.ymd(2020, 2, 1).and_hms(23, 59, 59)
+        );
+        assert_eq!(
+            end_of_day("2020-02-01T10:00:00+01:00").unwrap(),
+            Utc.ymd(2020, 2, 1).and_hms(9, 0, 0)
+        );
+        assert!(start_of_day("01/02/2020").is_err());
+    }
+}
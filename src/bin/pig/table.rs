@@ -0,0 +1,98 @@
+use std::fmt;
+
+// A plain text table with a header row. Columns holding amounts are aligned right
+pub struct Table {
+    headers: Vec<&'static str>,
+    right: Vec<bool>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn new(headers: &[&'static str]) -> Table {
+        Table {
+            headers: headers.to_vec(),
+            right: vec![false; headers.len()],
+            rows: Vec::new(),
+        }
+    }
+
+    pub fn align_right(mut self, columns: &[usize]) -> Table {
+        for column in columns {
+            self.right[*column] = true;
+        }
+        self
+    }
+
+    pub fn row(&mut self, cells: Vec<String>) {
+        self.rows.push(cells);
+    }
+
+    fn widths(&self) -> Vec<usize> {
+        let mut widths: Vec<usize> = self.headers.iter().map(|h| h.chars().count()).collect();
+
+        for row in &self.rows {
+            for (i, cell) in row.iter().enumerate() {
+                widths[i] = widths[i].max(cell.chars().count());
+            }
+        }
+
+        widths
+    }
+
+    fn write_row(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        cells: &[&str],
+        widths: &[usize],
+    ) -> fmt::Result {
+        let line: Vec<String> = cells
+            .iter()
+            .zip(widths)
+            .enumerate()
+            .map(|(i, (cell, width))| {
+                if self.right[i] {
+                    format!("{:>width$}", cell, width = width)
+                } else {
+                    format!("{:<width$}", cell, width = width)
+                }
+            })
+            .collect();
+
+        writeln!(f, "{}", line.join("  ").trim_end())
+    }
+}
+
+impl fmt::Display for Table {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let widths = self.widths();
+
+        self.write_row(f, &self.headers, &widths)?;
+
+        let rule: Vec<String> = widths.iter().map(|w| "-".repeat(*w)).collect();
+        writeln!(f, "{}", rule.join("  "))?;
+
+        for row in &self.rows {
+            let cells: Vec<&str> = row.iter().map(String::as_str).collect();
+            self.write_row(f, &cells, &widths)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn columns_fit_the_widest_cell() {
+        let mut table = Table::new(&["Name", "Balance"]).align_right(&[1]);
+        table.row(vec![String::from("Groceries"), String::from("3000")]);
+        table.row(vec![String::from("Food"), String::from("-12")]);
+
+        assert_eq!(
+            table.to_string(),
+            "Name       Balance\n---------  -------\nGroceries     3000\nFood           -12\n"
+        );
+    }
+}
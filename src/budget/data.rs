@@ -1,3 +1,4 @@
+use crate::money::{Amount, Money};
 use chrono::Utc;
 use serde_derive::{Deserialize, Serialize};
 
@@ -32,16 +33,21 @@ pub struct NewBudget {
     pub close: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize)]
 pub struct BudgetEntry {
     pub id: i32,
     pub account: i32,
     pub budget: i32,
-    pub balance: i32,
+    pub balance: Money,
+    // Debits - Credits of the account and all of its descendants
+    // during the budget period
+    pub spent: Money,
 }
 
+// The balance is given in minor units or as a decimal string
+// in the currency of the account
 #[derive(Debug, Deserialize)]
 pub struct NewBudgetEntry {
     pub account: i32,
-    pub balance: i32,
+    pub balance: Amount,
 }
@@ -1,43 +1,36 @@
-use crate::budget::data::{Budget, BudgetEntry, NewBudgetEntry};
+use crate::budget::data::{Budget, BudgetEntry};
 use crate::datastruct::SqlResult;
+use crate::error::{missing, LedgerError, Result};
+use crate::money::Money;
 use chrono::Utc;
-use rusqlite::{params, Result};
-use std::ops::DerefMut;
+use rusqlite::{params, Connection};
 
-pub fn get_budget(
-    conn: r2d2::PooledConnection<r2d2_sqlite::SqliteConnectionManager>,
-    id: i32,
-) -> Result<Budget> {
+pub fn get_budget(conn: &Connection, id: i32) -> Result<Budget> {
     let mut stmt = conn.prepare("SELECT id, name, open, close FROM Budgets WHERE id = ?1")?;
 
     stmt.query_row(params![id], |row| {
         Ok(Budget::new(
-            row.get(0).unwrap(),
-            &row.get(1).unwrap(),
-            row.get(2).unwrap(),
-            row.get(3).unwrap(),
+            row.get(0)?,
+            &row.get(1)?,
+            row.get(2)?,
+            row.get(3)?,
         ))
     })
+    .map_err(|e| missing(e, "Budget", id))
 }
 
-pub fn remove_budget(
-    mut conn: r2d2::PooledConnection<r2d2_sqlite::SqliteConnectionManager>,
-    id: i32,
-) -> Result<()> {
-    let con = conn.deref_mut();
-    let tx = con.transaction()?;
+pub fn remove_budget(conn: &mut Connection, id: i32) -> Result<()> {
+    let tx = conn.transaction()?;
 
     tx.execute("DELETE FROM Budgets WHERE id = ?1", params![id])?;
 
-    tx.commit()
+    tx.commit()?;
+
+    Ok(())
 }
 
-pub fn create_budget(
-    mut conn: r2d2::PooledConnection<r2d2_sqlite::SqliteConnectionManager>,
-    budget: &Budget,
-) -> Result<i64> {
-    let con = conn.deref_mut();
-    let tx = con.transaction()?;
+pub fn create_budget(conn: &mut Connection, budget: &Budget) -> Result<i64> {
+    let tx = conn.transaction()?;
 
     tx.execute(
         "INSERT INTO Budgets (name, open, close) VALUES (?1, ?2, ?3)",
@@ -46,16 +39,13 @@ pub fn create_budget(
 
     let budget_id = tx.last_insert_rowid();
 
-    let transaction_result = tx.commit();
+    tx.commit()?;
 
-    match transaction_result {
-        Ok(_) => Ok(budget_id),
-        Err(_) => panic!("Budget creation has failed"),
-    }
+    Ok(budget_id)
 }
 
 pub fn get_budget_by_date(
-    conn: r2d2::PooledConnection<r2d2_sqlite::SqliteConnectionManager>,
+    conn: &Connection,
     start: chrono::DateTime<Utc>,
     end: chrono::DateTime<Utc>,
 ) -> Result<Budget> {
@@ -63,29 +53,31 @@ pub fn get_budget_by_date(
 
     stmt.query_row(params![start, end], |row| {
         Ok(Budget::new(
-            row.get(0).unwrap(),
-            &row.get(1).unwrap(),
-            row.get(2).unwrap(),
-            row.get(3).unwrap(),
+            row.get(0)?,
+            &row.get(1)?,
+            row.get(2)?,
+            row.get(3)?,
         ))
     })
+    .map_err(|e| match e {
+        rusqlite::Error::QueryReturnedNoRows => {
+            LedgerError::NotFound(format!("No budget found for {} - {}", start, end))
+        }
+        e => LedgerError::from(e),
+    })
 }
 
 pub fn check_if_budget_exists(
-    conn: r2d2::PooledConnection<r2d2_sqlite::SqliteConnectionManager>,
+    conn: &Connection,
     start: chrono::DateTime<Utc>,
     end: chrono::DateTime<Utc>,
 ) -> Result<bool> {
     let mut stmt =
         conn.prepare("SELECT EXISTS(SELECT * from Budgets WHERE open >= ?1 AND close < ?2);")?;
 
-    let result = stmt
-        .query_row(params![start, end], |row| {
-            Ok(SqlResult {
-                value: row.get(0).unwrap(),
-            })
-        })
-        .unwrap();
+    let result = stmt.query_row(params![start, end], |row| {
+        Ok(SqlResult { value: row.get(0)? })
+    })?;
 
     if result.value == 0 {
         return Ok(false);
@@ -95,84 +87,108 @@ pub fn check_if_budget_exists(
 }
 
 pub fn add_budget_entry(
-    mut conn: r2d2::PooledConnection<r2d2_sqlite::SqliteConnectionManager>,
+    conn: &mut Connection,
     budget_id: i32,
-    entry: NewBudgetEntry,
+    account: i32,
+    balance: i64,
 ) -> Result<()> {
-    let con = conn.deref_mut();
-    let tx = con.transaction()?;
+    let tx = conn.transaction()?;
 
     tx.execute(
         "INSERT INTO BudgetEntries (account, budget, balance) VALUES (?1, ?2, ?3)",
-        params![entry.account, budget_id, entry.balance],
+        params![account, budget_id, balance],
     )?;
 
-    tx.commit()
+    tx.commit()?;
+
+    Ok(())
 }
 
 pub fn update_budget_entry(
-    mut conn: r2d2::PooledConnection<r2d2_sqlite::SqliteConnectionManager>,
+    conn: &mut Connection,
     budget_id: i32,
-    entry: NewBudgetEntry,
+    account: i32,
+    balance: i64,
 ) -> Result<()> {
-    let con = conn.deref_mut();
-    let tx = con.transaction()?;
+    let tx = conn.transaction()?;
 
     tx.execute(
         "UPDATE BudgetEntries SET balance = ?1 WHERE account = ?2 AND budget = ?3",
-        params![entry.balance, entry.account, budget_id],
+        params![balance, account, budget_id],
     )?;
 
-    tx.commit()
+    tx.commit()?;
+
+    Ok(())
 }
 
-pub fn delete_budget_entry(
-    mut conn: r2d2::PooledConnection<r2d2_sqlite::SqliteConnectionManager>,
-    budget_id: i32,
-    entry: NewBudgetEntry,
-) -> Result<()> {
-    let con = conn.deref_mut();
-    let tx = con.transaction()?;
+pub fn delete_budget_entry(conn: &mut Connection, budget_id: i32, account: i32) -> Result<()> {
+    let tx = conn.transaction()?;
 
     tx.execute(
         "DELETE FROM BudgetEntries WHERE account = ?1 AND budget = ?2",
-        params![entry.account, budget_id],
+        params![account, budget_id],
     )?;
 
-    tx.commit()
+    tx.commit()?;
+
+    Ok(())
 }
 
-pub fn list_budget_entries(
-    conn: r2d2::PooledConnection<r2d2_sqlite::SqliteConnectionManager>,
-    budget: i32,
-) -> Result<Vec<BudgetEntry>> {
-    let mut stmt =
-        conn.prepare("SELECT id, account, budget, balance FROM BudgetEntries WHERE budget = ?1;")?;
+pub fn list_budget_entries(conn: &Connection, budget: i32) -> Result<Vec<BudgetEntry>> {
+    let mut stmt = conn.prepare(
+        "WITH RECURSIVE tree(root, id) AS (
+            SELECT account, account FROM BudgetEntries WHERE budget = ?1
+            UNION
+            SELECT tree.root, a.id FROM Accounts as a INNER JOIN tree ON a.parent = tree.id
+        )
+        SELECT e.id, e.account, e.budget, e.balance, a.currency, cu.minor_unit,
+        (SELECT ifnull(SUM(d.balance),0) FROM Debits as d
+            INNER JOIN Transactions as t ON d.transaction_id = t.id
+            INNER JOIN tree ON tree.id = d.account
+            WHERE tree.root = e.account
+            AND julianday(t.date) >= julianday(b.open) AND julianday(t.date) < julianday(b.close, '+1 day')),
+        (SELECT ifnull(SUM(c.balance),0) FROM Credits as c
+            INNER JOIN Transactions as t ON c.transaction_id = t.id
+            INNER JOIN tree ON tree.id = c.account
+            WHERE tree.root = e.account
+            AND julianday(t.date) >= julianday(b.open) AND julianday(t.date) < julianday(b.close, '+1 day'))
+        FROM BudgetEntries as e INNER JOIN Budgets as b ON e.budget = b.id
+        INNER JOIN Accounts as a ON e.account = a.id
+        INNER JOIN Currency as cu ON a.currency = cu.code
+        WHERE e.budget = ?1;",
+    )?;
 
-    let result = stmt
+    let rows = stmt
         .query_map(params![budget], |row| {
-            Ok(BudgetEntry {
-                id: row.get(0).unwrap(),
-                account: row.get(1).unwrap(),
-                budget: row.get(2).unwrap(),
-                balance: row.get(3).unwrap(),
-            })
+            let currency: String = row.get(4)?;
+            let minor_unit = row.get(5)?;
+            let credits: i64 = row.get(7)?;
+
+            // spent holds the debits until the credits are taken off below
+            let entry = BudgetEntry {
+                id: row.get(0)?,
+                account: row.get(1)?,
+                budget: row.get(2)?,
+                balance: Money::new(row.get(3)?, &currency, minor_unit),
+                spent: Money::new(row.get(6)?, &currency, minor_unit),
+            };
+
+            Ok((entry, credits))
+        })?
+        .collect::<rusqlite::Result<Vec<(BudgetEntry, i64)>>>()?;
+
+    rows.into_iter()
+        .map(|(mut entry, credits)| {
+            entry.spent = entry.spent.checked_sub(credits)?;
+
+            Ok(entry)
         })
-        .and_then(|mapped_rows| {
-            Ok(mapped_rows
-                .map(|row| row.unwrap())
-                .collect::<Vec<BudgetEntry>>())
-        })?;
-
-    Ok(result)
+        .collect()
 }
 
-pub fn generate_budget(
-    mut conn: r2d2::PooledConnection<r2d2_sqlite::SqliteConnectionManager>,
-    budget: &Budget,
-) -> Result<i64> {
-    let con = conn.deref_mut();
-    let tx = con.transaction()?;
+pub fn generate_budget(conn: &mut Connection, budget: &Budget) -> Result<i64> {
+    let tx = conn.transaction()?;
 
     tx.execute(
         "INSERT INTO Budgets (name, open, close) VALUES (?1, ?2, ?3)",
@@ -187,10 +203,43 @@ pub fn generate_budget(
         params![budget_id],
     )?;
 
-    let transaction_result = tx.commit();
+    tx.commit()?;
+
+    Ok(budget_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn budget_entries_include_spending_of_child_accounts() {
+        let pool = crate::migrations::memory_pool();
+        let conn = pool.get().unwrap();
+
+        conn.execute_batch(
+            "INSERT INTO Accounts (type, name, currency, parent) VALUES
+                (0, 'Current', 'GBP', NULL), (4, 'Food', 'GBP', NULL), (4, 'Groceries', 'GBP', 2), (4, 'Fruit', 'GBP', 3);
+            INSERT INTO Budgets (name, open, close) VALUES ('January', '2020-01-01T00:00:00+00:00', '2020-01-31T00:00:00+00:00');
+            INSERT INTO BudgetEntries (account, budget, balance) VALUES (2, 1, 30000), (3, 1, 20000);
+
+            BEGIN;
+            INSERT INTO Transactions (date, name) VALUES
+                ('2020-01-05T10:00:00+00:00', 'Lunch'),
+                ('2020-01-31T18:00:00+00:00', 'Weekly shop'),
+                ('2020-02-01T10:00:00+00:00', 'Too late');
+            INSERT INTO Debits (account, transaction_id, balance) VALUES (2, 1, 1000), (3, 2, 4000), (4, 2, 500), (3, 3, 9999);
+            INSERT INTO Credits (account, transaction_id, balance) VALUES (1, 1, 1000), (1, 2, 4500), (1, 3, 9999);
+            COMMIT;",
+        )
+        .unwrap();
+
+        let entries = list_budget_entries(&conn, 1).unwrap();
+
+        let food = entries.iter().find(|e| e.account == 2).unwrap();
+        let groceries = entries.iter().find(|e| e.account == 3).unwrap();
 
-    match transaction_result {
-        Ok(_) => Ok(budget_id),
-        Err(_) => panic!("Budget creation has failed"),
+        assert_eq!(food.spent.amount, 5500);
+        assert_eq!(groceries.spent.amount, 4500);
     }
 }
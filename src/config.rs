@@ -0,0 +1,277 @@
+use actix_web::http::Uri;
+use serde_derive::Deserialize;
+use std::fmt;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use structopt::StructOpt;
+
+// Read when no config file is given explicitly
+const DEFAULT_CONFIG_FILE: &str = "ledger.toml";
+
+#[derive(Debug, PartialEq, Eq, Deserialize, Copy, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<LogFormat, String> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("Unknown log format: {}", s)),
+        }
+    }
+}
+
+// Settings of a server instance. Every value can come from the config file,
+// an environment variable or a command line flag, in increasing precedence
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub database: PathBuf,
+    pub host: String,
+    pub port: u16,
+    // Origins allowed to call the API from a browser. "*" allows any origin,
+    // an empty list disables CORS
+    pub cors_origins: Vec<String>,
+    pub pool_size: u32,
+    // env_logger filters, e.g. "info" or "info,actix_web=warn"
+    pub log_level: String,
+    pub log_format: LogFormat,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            database: PathBuf::from("ledger.db"),
+            host: String::from("localhost"),
+            port: 8088,
+            cors_origins: vec![String::from("*")],
+            pool_size: 10,
+            log_level: String::from("info"),
+            log_format: LogFormat::Text,
+        }
+    }
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "ledger", about = "The PIG ledger server")]
+pub struct Args {
+    /// TOML config file. Defaults to ledger.toml if it exists
+    #[structopt(short, long, env = "LEDGER_CONFIG", parse(from_os_str))]
+    pub config: Option<PathBuf>,
+    /// Path of the sqlite database
+    #[structopt(long, env = "LEDGER_DATABASE", parse(from_os_str))]
+    pub database: Option<PathBuf>,
+    /// Address to listen on
+    #[structopt(long, env = "LEDGER_HOST")]
+    pub host: Option<String>,
+    /// Port to listen on
+    #[structopt(short, long, env = "LEDGER_PORT")]
+    pub port: Option<u16>,
+    /// Comma separated origins allowed by CORS, * for any
+    #[structopt(long, env = "LEDGER_CORS_ORIGINS", use_delimiter = true)]
+    pub cors_origins: Option<Vec<String>>,
+    /// Maximum number of database connections
+    #[structopt(long, env = "LEDGER_POOL_SIZE")]
+    pub pool_size: Option<u32>,
+    /// Log filters, e.g. info or info,actix_web=warn
+    #[structopt(long, env = "LEDGER_LOG_LEVEL")]
+    pub log_level: Option<String>,
+    /// Log format, text or json
+    #[structopt(long, env = "LEDGER_LOG_FORMAT")]
+    pub log_format: Option<LogFormat>,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(PathBuf, std::io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read(path, e) => write!(f, "Can't read {}: {}", path.display(), e),
+            ConfigError::Parse(path, e) => write!(f, "Invalid config {}: {}", path.display(), e),
+            ConfigError::Invalid(m) => write!(f, "{}", m),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    pub fn from_file(path: &Path) -> Result<Config, ConfigError> {
+        let content =
+            std::fs::read_to_string(path).map_err(|e| ConfigError::Read(path.to_owned(), e))?;
+
+        toml::from_str(&content).map_err(|e| ConfigError::Parse(path.to_owned(), e))
+    }
+
+    // Reads the config file, if there is one, and applies the environment
+    // and command line on top of it
+    pub fn load(args: Args) -> Result<Config, ConfigError> {
+        let config = match &args.config {
+            Some(path) => Config::from_file(path)?,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                Config::from_file(Path::new(DEFAULT_CONFIG_FILE))?
+            }
+            None => Config::default(),
+        };
+
+        let config = config.merge(args);
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    fn merge(self, args: Args) -> Config {
+        Config {
+            database: args.database.unwrap_or(self.database),
+            host: args.host.unwrap_or(self.host),
+            port: args.port.unwrap_or(self.port),
+            // An empty value from the environment leaves an empty origin behind
+            cors_origins: args
+                .cors_origins
+                .map(|o| o.into_iter().filter(|o| !o.is_empty()).collect())
+                .unwrap_or(self.cors_origins),
+            pool_size: args.pool_size.unwrap_or(self.pool_size),
+            log_level: args.log_level.unwrap_or(self.log_level),
+            log_format: args.log_format.unwrap_or(self.log_format),
+        }
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.pool_size == 0 {
+            return Err(ConfigError::Invalid(String::from(
+                "pool_size has to be at least 1",
+            )));
+        }
+
+        if self.host.is_empty() {
+            return Err(ConfigError::Invalid(String::from("host can not be empty")));
+        }
+
+        // actix-cors only reports an invalid origin by panicking once the
+        // server starts, so they are checked upfront
+        if let Some(origin) = self
+            .cors_origins
+            .iter()
+            .find(|o| *o != "*" && o.parse::<Uri>().is_err())
+        {
+            return Err(ConfigError::Invalid(format!(
+                "cors_origins contains an invalid origin: {}",
+                origin
+            )));
+        }
+
+        Ok(())
+    }
+
+    pub fn bind_address(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+
+    pub fn init_logger(&self) {
+        let mut builder = env_logger::Builder::new();
+        builder.parse_filters(&self.log_level);
+
+        if self.log_format == LogFormat::Json {
+            builder.format(|buf, record| {
+                let line = serde_json::json!({
+                    "timestamp": chrono::Utc::now().to_rfc3339(),
+                    "level": record.level().to_string(),
+                    "target": record.target(),
+                    "message": record.args().to_string(),
+                });
+                writeln!(buf, "{}", line)
+            });
+        }
+
+        builder.init();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(flags: &[&str]) -> Args {
+        Args::from_iter_safe(std::iter::once("ledger").chain(flags.iter().cloned())).unwrap()
+    }
+
+    #[test]
+    fn missing_values_fall_back_to_defaults() {
+        let config: Config = toml::from_str("port = 9000\nlog_format = \"json\"").unwrap();
+
+        assert_eq!(config.port, 9000);
+        assert_eq!(config.log_format, LogFormat::Json);
+        assert_eq!(config.database, PathBuf::from("ledger.db"));
+        assert_eq!(config.bind_address(), "localhost:9000");
+        assert_eq!(config.cors_origins, vec!["*"]);
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        let config: Result<Config, _> = toml::from_str("prot = 9000");
+
+        assert!(config.is_err());
+    }
+
+    #[test]
+    fn flags_override_the_file() {
+        let file: Config = toml::from_str(
+            "database = \"team.db\"\nport = 9000\ncors_origins = [\"https://pig.example\"]",
+        )
+        .unwrap();
+
+        let config = file.merge(args(&[
+            "--port",
+            "9100",
+            "--cors-origins",
+            "https://a.example,https://b.example",
+        ]));
+
+        assert_eq!(config.database, PathBuf::from("team.db"));
+        assert_eq!(config.port, 9100);
+        assert_eq!(
+            config.cors_origins,
+            vec!["https://a.example", "https://b.example"]
+        );
+    }
+
+    #[test]
+    fn empty_origins_disable_cors() {
+        let config = Config::default().merge(args(&["--cors-origins", ""]));
+
+        assert!(config.cors_origins.is_empty());
+    }
+
+    #[test]
+    fn empty_pool_is_invalid() {
+        let config = Config::default().merge(args(&["--pool-size", "0"]));
+
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn malformed_origins_are_invalid() {
+        let config = Config::default().merge(args(&["--cors-origins", "https://bad host"]));
+
+        assert!(config.validate().is_err());
+
+        let config = Config::default().merge(args(&[
+            "--cors-origins",
+            "https://pig.example,http://localhost:3000",
+        ]));
+
+        assert!(config.validate().is_ok());
+    }
+}
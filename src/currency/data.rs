@@ -0,0 +1,108 @@
+use serde_derive::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct Currency {
+    pub code: String,
+    pub numeric_code: i32,
+    // Number of decimal places, e.g. 2 for 12.34 GBP
+    pub minor_unit: i32,
+    pub name: String,
+}
+
+// Replaces everything but the code of a currency
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateCurrency {
+    pub numeric_code: i32,
+    pub minor_unit: i32,
+    pub name: String,
+}
+
+// No ISO 4217 currency has more than four decimal places
+pub const MAX_MINOR_UNIT: i32 = 4;
+
+// Codes are three upper case letters, e.g. GBP
+pub fn valid_code(code: &str) -> bool {
+    code.len() == 3 && code.chars().all(|c| c.is_ascii_uppercase())
+}
+
+fn check_details(numeric_code: i32, minor_unit: i32, name: &str) -> Result<(), String> {
+    if !(1..=999).contains(&numeric_code) {
+        return Err(String::from("numeric_code has to be between 1 and 999"));
+    }
+
+    if !(0..=MAX_MINOR_UNIT).contains(&minor_unit) {
+        return Err(format!(
+            "minor_unit has to be between 0 and {}",
+            MAX_MINOR_UNIT
+        ));
+    }
+
+    if name.trim().is_empty() {
+        return Err(String::from("Currency name can not be empty"));
+    }
+
+    Ok(())
+}
+
+impl Currency {
+    // Describes the first invalid field, if there is one
+    pub fn check(&self) -> Result<(), String> {
+        if !valid_code(&self.code) {
+            return Err(String::from(
+                "code has to be three upper case letters, e.g. GBP",
+            ));
+        }
+
+        check_details(self.numeric_code, self.minor_unit, &self.name)
+    }
+}
+
+impl UpdateCurrency {
+    pub fn check(&self) -> Result<(), String> {
+        check_details(self.numeric_code, self.minor_unit, &self.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn currency(code: &str, numeric_code: i32, minor_unit: i32) -> Currency {
+        Currency {
+            code: String::from(code),
+            numeric_code,
+            minor_unit,
+            name: String::from("Test"),
+        }
+    }
+
+    #[test]
+    fn codes_are_three_upper_case_letters() {
+        assert!(currency("GBP", 826, 2).check().is_ok());
+        assert!(currency("gbp", 826, 2).check().is_err());
+        assert!(currency("GB", 826, 2).check().is_err());
+        assert!(currency("GBPX", 826, 2).check().is_err());
+        assert!(currency("G1P", 826, 2).check().is_err());
+    }
+
+    #[test]
+    fn numeric_code_and_minor_unit_are_bounded() {
+        assert!(currency("JPY", 392, 0).check().is_ok());
+        assert!(currency("CLF", 990, 4).check().is_ok());
+        assert!(currency("XXA", 0, 2).check().is_err());
+        assert!(currency("XXA", 1000, 2).check().is_err());
+        assert!(currency("XXA", 999, -1).check().is_err());
+        assert!(currency("XXA", 999, 5).check().is_err());
+    }
+
+    #[test]
+    fn names_can_not_be_blank() {
+        let update = UpdateCurrency {
+            numeric_code: 826,
+            minor_unit: 2,
+            name: String::from("  "),
+        };
+
+        assert!(update.check().is_err());
+    }
+}
@@ -0,0 +1,217 @@
+use crate::currency::data::{Currency, UpdateCurrency};
+use crate::error::{missing, LedgerError, Result};
+use rusqlite::{params, Connection, NO_PARAMS};
+
+// The same table the schema migrations seed a new ledger with
+const ISO_4217: &str = include_str!("../../data/migrations/0004_iso_4217.sql");
+
+pub fn get_currency(conn: &Connection, code: &str) -> Result<Currency> {
+    conn.query_row(
+        "SELECT code, numeric_code, minor_unit, name FROM Currency WHERE code = ?1",
+        params![code],
+        |row| {
+            Ok(Currency {
+                code: row.get(0)?,
+                numeric_code: row.get(1)?,
+                minor_unit: row.get(2)?,
+                name: row.get(3)?,
+            })
+        },
+    )
+    .map_err(|e| missing(e, "Currency", code))
+}
+
+pub fn list_currencies(conn: &Connection) -> Result<Vec<Currency>> {
+    let mut stmt = conn.prepare("SELECT code, numeric_code, minor_unit, name FROM Currency")?;
+
+    let result = stmt
+        .query_map(NO_PARAMS, |row| {
+            Ok(Currency {
+                code: row.get(0)?,
+                numeric_code: row.get(1)?,
+                minor_unit: row.get(2)?,
+                name: row.get(3)?,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<Currency>>>()?;
+
+    Ok(result)
+}
+
+pub fn add_currency(conn: &mut Connection, currency: &Currency) -> Result<()> {
+    let tx = conn.transaction()?;
+
+    tx.execute(
+        "INSERT INTO Currency (code, numeric_code, minor_unit, name) VALUES (?1, ?2, ?3, ?4)",
+        params![
+            currency.code,
+            currency.numeric_code,
+            currency.minor_unit,
+            currency.name
+        ],
+    )?;
+
+    tx.commit()?;
+
+    Ok(())
+}
+
+pub fn update_currency(conn: &mut Connection, code: &str, update: &UpdateCurrency) -> Result<()> {
+    let tx = conn.transaction()?;
+
+    let updated = tx.execute(
+        "UPDATE Currency SET numeric_code = ?1, minor_unit = ?2, name = ?3 WHERE code = ?4",
+        params![update.numeric_code, update.minor_unit, update.name, code],
+    )?;
+
+    if updated == 0 {
+        return Err(LedgerError::not_found("Currency", code));
+    }
+
+    tx.commit()?;
+
+    Ok(())
+}
+
+// Returns true if any account holds the currency
+pub fn currency_in_use(conn: &Connection, code: &str) -> Result<bool> {
+    let in_use = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM Accounts WHERE currency = ?1)",
+        params![code],
+        |row| row.get(0),
+    )?;
+
+    Ok(in_use)
+}
+
+pub fn remove_currency(conn: &mut Connection, code: &str) -> Result<()> {
+    let tx = conn.transaction()?;
+
+    let deleted = tx.execute("DELETE FROM Currency WHERE code = ?1", params![code])?;
+
+    if deleted == 0 {
+        return Err(LedgerError::not_found("Currency", code));
+    }
+
+    tx.commit()?;
+
+    Ok(())
+}
+
+// Adds every ISO 4217 currency the ledger does not know yet and returns how
+// many were added. Existing currencies, including changed ones, are kept
+pub fn import_iso_4217(conn: &mut Connection) -> Result<u32> {
+    let tx = conn.transaction()?;
+
+    let count = |tx: &Connection| -> rusqlite::Result<u32> {
+        tx.query_row("SELECT COUNT(*) FROM Currency", NO_PARAMS, |row| row.get(0))
+    };
+
+    let before = count(&tx)?;
+    tx.execute_batch(ISO_4217)?;
+    let after = count(&tx)?;
+
+    tx.commit()?;
+
+    Ok(after - before)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations;
+
+    fn test_currency() -> Currency {
+        Currency {
+            code: String::from("XTS"),
+            numeric_code: 963,
+            minor_unit: 3,
+            name: String::from("Test Currency"),
+        }
+    }
+
+    #[test]
+    fn lists_currencies_returns_struct() {
+        let pool = migrations::memory_pool();
+
+        let expected = Currency {
+            code: String::from("GBP"),
+            numeric_code: 826,
+            minor_unit: 2,
+            name: String::from("Pound Sterling"),
+        };
+        let result = list_currencies(&pool.get().unwrap()).unwrap();
+
+        assert!(result.contains(&expected));
+    }
+
+    #[test]
+    fn list_currencies_can_return_multiple_currencies() {
+        let pool = migrations::memory_pool();
+
+        let result = list_currencies(&pool.get().unwrap()).unwrap();
+        let codes: Vec<&str> = result.iter().map(|c| c.code.as_str()).collect();
+
+        assert!(codes.contains(&"GBP"));
+        assert!(codes.contains(&"EUR"));
+        assert!(codes.contains(&"PLN"));
+    }
+
+    #[test]
+    fn currencies_can_be_added_updated_and_removed() {
+        let pool = migrations::memory_pool();
+        let mut conn = pool.get().unwrap();
+
+        add_currency(&mut conn, &test_currency()).unwrap();
+        assert_eq!(get_currency(&conn, "XTS").unwrap(), test_currency());
+
+        update_currency(
+            &mut conn,
+            "XTS",
+            &UpdateCurrency {
+                numeric_code: 963,
+                minor_unit: 2,
+                name: String::from("Testing"),
+            },
+        )
+        .unwrap();
+        assert_eq!(get_currency(&conn, "XTS").unwrap().minor_unit, 2);
+
+        remove_currency(&mut conn, "XTS").unwrap();
+        match get_currency(&conn, "XTS") {
+            Err(LedgerError::NotFound(m)) => assert_eq!(m, "Currency XTS not found"),
+            other => panic!("Expected not found, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn duplicate_numeric_codes_conflict() {
+        let pool = migrations::memory_pool();
+        let mut conn = pool.get().unwrap();
+
+        let mut currency = test_currency();
+        currency.numeric_code = 826;
+
+        match add_currency(&mut conn, &currency) {
+            Err(LedgerError::Conflict(_)) => {}
+            other => panic!("Expected a conflict, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn import_restores_missing_currencies_only() {
+        let pool = migrations::memory_pool();
+        let mut conn = pool.get().unwrap();
+
+        conn.execute_batch(
+            "DELETE FROM Currency WHERE code IN ('CHF', 'JPY');
+            UPDATE Currency SET name = 'Sterling' WHERE code = 'GBP';",
+        )
+        .unwrap();
+
+        assert_eq!(import_iso_4217(&mut conn).unwrap(), 2);
+        assert_eq!(import_iso_4217(&mut conn).unwrap(), 0);
+        assert_eq!(get_currency(&conn, "JPY").unwrap().minor_unit, 0);
+        assert_eq!(get_currency(&conn, "GBP").unwrap().name, "Sterling");
+    }
+}
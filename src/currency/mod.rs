@@ -0,0 +1,60 @@
+use actix_web::{web, HttpResponse};
+use serde_json::json;
+
+pub mod data;
+pub mod db;
+
+use crate::datastruct;
+use crate::error::Result;
+use crate::Ledger;
+
+pub async fn list_currencies(ledger: web::Data<Ledger>) -> Result<HttpResponse> {
+    let result = ledger.currencies()?;
+
+    Ok(HttpResponse::Ok().json(result))
+}
+
+pub async fn get_currency(
+    params: web::Path<datastruct::CodeRequest>,
+    ledger: web::Data<Ledger>,
+) -> Result<HttpResponse> {
+    let result = ledger.currency(&params.code)?;
+
+    Ok(HttpResponse::Ok().json(result))
+}
+
+pub async fn create_currency(
+    currency: web::Json<data::Currency>,
+    ledger: web::Data<Ledger>,
+) -> Result<HttpResponse> {
+    let result = ledger.create_currency(&currency)?;
+
+    Ok(HttpResponse::Created().json(result))
+}
+
+pub async fn update_currency(
+    params: web::Path<datastruct::CodeRequest>,
+    currency: web::Json<data::UpdateCurrency>,
+    ledger: web::Data<Ledger>,
+) -> Result<HttpResponse> {
+    let result = ledger.update_currency(&params.code, &currency)?;
+
+    Ok(HttpResponse::Ok().json(result))
+}
+
+// Only currencies that no account holds can be deleted
+pub async fn delete_currency(
+    params: web::Path<datastruct::CodeRequest>,
+    ledger: web::Data<Ledger>,
+) -> Result<HttpResponse> {
+    ledger.delete_currency(&params.code)?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+// Adds the ISO 4217 currencies the ledger is missing
+pub async fn import_currencies(ledger: web::Data<Ledger>) -> Result<HttpResponse> {
+    let imported = ledger.import_currencies()?;
+
+    Ok(HttpResponse::Ok().json(json!({ "imported": imported })))
+}
@@ -1,4 +1,5 @@
-use chrono::Utc;
+use crate::transaction::data::EntryType;
+use chrono::{DateTime, Datelike, Utc};
 use serde_derive::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -7,27 +8,172 @@ pub struct IdRequest {
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-pub struct DateRequest {
-    pub year: i32,
-    pub month: u8,
+pub struct CodeRequest {
+    pub code: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SqlResult {
+    pub value: i64,
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq, Copy, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum FindingKind {
+    // Debits and credits of a transaction don't add up
+    Unbalanced,
+    // A transaction without any entries
+    Empty,
+    // A transaction across currencies does not balance in one of them
+    UnbalancedCurrency,
+    // An entry of a transaction that does not exist
+    OrphanedEntry,
+    // An entry in an account that does not exist
+    MissingAccount,
 }
 
 #[derive(Debug, Serialize)]
-pub struct Transaction {
+pub struct Finding {
+    pub kind: FindingKind,
+    pub transaction: Option<i32>,
+    pub entry: Option<i32>,
+    pub entry_type: Option<EntryType>,
+    pub account: Option<i32>,
+    pub message: String,
+}
+
+// Debit and credit totals of a single transaction
+#[derive(Debug)]
+pub struct TransactionTotals {
     pub id: i32,
-    pub date: chrono::DateTime<Utc>,
-    pub name: String,
+    pub debits: i64,
+    pub credits: i64,
+    pub entries: i64,
+}
+
+impl TransactionTotals {
+    pub fn finding(&self) -> Option<Finding> {
+        let (kind, message) = if self.entries == 0 {
+            (
+                FindingKind::Empty,
+                format!("Transaction {} has no entries", self.id),
+            )
+        } else if self.debits != self.credits {
+            (
+                FindingKind::Unbalanced,
+                format!(
+                    "Transaction {} has {} in debits and {} in credits",
+                    self.id, self.debits, self.credits
+                ),
+            )
+        } else {
+            return None;
+        };
+
+        Some(Finding {
+            kind,
+            transaction: Some(self.id),
+            entry: None,
+            entry_type: None,
+            account: None,
+            message,
+        })
+    }
 }
 
 #[derive(Debug, Serialize)]
-pub struct SqlResult {
-    pub value: i32,
+pub struct IntegrityReport {
+    // True when there are no findings
+    pub integrity: bool,
+    pub transactions: usize,
+    pub findings: Vec<Finding>,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
-pub struct Currency {
-    pub code: String,
-    pub numeric_code: i32,
-    pub minor_unit: i32,
-    pub name: String,
+impl IntegrityReport {
+    pub fn new(totals: &[TransactionTotals], other: Vec<Finding>) -> IntegrityReport {
+        let mut findings: Vec<Finding> = totals.iter().filter_map(|t| t.finding()).collect();
+        findings.extend(other);
+
+        IntegrityReport {
+            integrity: findings.is_empty(),
+            transactions: totals.len(),
+            findings,
+        }
+    }
+}
+
+// Parses an RFC 3339 date. Dates before 1970 are not valid in the ledger
+pub fn parse_date(value: &str) -> Option<DateTime<Utc>> {
+    let date = DateTime::parse_from_rfc3339(value)
+        .ok()?
+        .with_timezone(&Utc);
+
+    if date.year() < 1970 {
+        return None;
+    }
+
+    Some(date)
+}
+
+// Query for reports calculated as of a point in time
+#[derive(Debug, Deserialize)]
+pub struct AsOfQuery {
+    date: Option<String>,
+}
+
+impl AsOfQuery {
+    pub fn is_empty(&self) -> bool {
+        self.date.is_none()
+    }
+
+    // Returns true if no date was supplied or the supplied one is valid
+    pub fn valid_date(&self) -> bool {
+        match &self.date {
+            Some(v) => parse_date(v).is_some(),
+            None => true,
+        }
+    }
+
+    // Defaults to now when not supplied
+    pub fn date(&self) -> DateTime<Utc> {
+        self.date
+            .as_ref()
+            .and_then(|v| parse_date(v))
+            .unwrap_or_else(Utc::now)
+    }
+}
+
+// Query for reports calculated over a period of time
+#[derive(Debug, Deserialize)]
+pub struct RangeQuery {
+    from: String,
+    to: Option<String>,
+}
+
+impl RangeQuery {
+    // Returns true if both dates are valid and the range is not reversed
+    pub fn valid_range(&self) -> bool {
+        let from = parse_date(&self.from);
+        let to_valid = match &self.to {
+            Some(v) => parse_date(v).is_some(),
+            None => true,
+        };
+
+        match from {
+            Some(v) => to_valid && v <= self.to(),
+            None => false,
+        }
+    }
+
+    pub fn from(&self) -> Option<DateTime<Utc>> {
+        parse_date(&self.from)
+    }
+
+    // Defaults to now when not supplied
+    pub fn to(&self) -> DateTime<Utc> {
+        self.to
+            .as_ref()
+            .and_then(|v| parse_date(v))
+            .unwrap_or_else(Utc::now)
+    }
 }
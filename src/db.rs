@@ -1,136 +1,348 @@
-use crate::datastruct::{Currency, SqlResult};
+use crate::datastruct::{Finding, FindingKind, IntegrityReport, SqlResult, TransactionTotals};
+use crate::transaction::data::EntryType;
 
-use rusqlite::{params, Result, NO_PARAMS};
+use crate::error::Result;
+use crate::money;
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, NO_PARAMS};
 
-// SELECT SUM(c.balance) - SUM(d.balance) FROM Credits as c, Debits as d;
-pub fn check_integrity(
-    conn: r2d2::PooledConnection<r2d2_sqlite::SqliteConnectionManager>,
-) -> Result<bool> {
-    let mut stmt =
-        conn.prepare("SELECT SUM(c.balance) - SUM(d.balance) FROM Credits as c, Debits as d")?;
+// Sets up every new connection of the pool. SQLite only enforces
+// foreign keys, and with them the ledger constraints, when asked to
+pub fn init_connection(conn: &rusqlite::Connection) -> rusqlite::Result<()> {
+    conn.execute_batch("PRAGMA foreign_keys = ON;")
+}
+
+// Checks every transaction and entry of the ledger
+pub fn check_integrity(conn: &Connection) -> Result<IntegrityReport> {
+    let mut stmt = conn.prepare(
+        "SELECT t.id,
+        (SELECT ifnull(SUM(balance),0) FROM Debits WHERE transaction_id = t.id),
+        (SELECT ifnull(SUM(balance),0) FROM Credits WHERE transaction_id = t.id),
+        (SELECT COUNT(*) FROM Debits WHERE transaction_id = t.id)
+            + (SELECT COUNT(*) FROM Credits WHERE transaction_id = t.id)
+        FROM Transactions as t ORDER BY t.id",
+    )?;
+
+    let totals = stmt
+        .query_map(NO_PARAMS, |row| {
+            Ok(TransactionTotals {
+                id: row.get(0)?,
+                debits: row.get(1)?,
+                credits: row.get(2)?,
+                entries: row.get(3)?,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<TransactionTotals>>>()?;
+
+    let mut findings = Vec::new();
 
-    let query = stmt
+    // Transactions across currencies have to balance in each of them.
+    // Transactions in a single currency are covered by the totals
+    let mut stmt = conn.prepare(
+        "SELECT transaction_id, group_concat(CASE WHEN imbalance <> 0 THEN currency END) FROM
+            (SELECT e.transaction_id, a.currency, SUM(e.amount) as imbalance FROM
+                (SELECT transaction_id, account, balance as amount FROM Debits
+                UNION ALL
+                SELECT transaction_id, account, -balance as amount FROM Credits) as e
+            INNER JOIN Accounts as a ON a.id = e.account
+            GROUP BY e.transaction_id, a.currency)
+        GROUP BY transaction_id HAVING COUNT(*) > 1 AND SUM(imbalance <> 0) > 0
+        ORDER BY transaction_id",
+    )?;
+
+    let unbalanced = stmt
         .query_map(NO_PARAMS, |row| {
-            Ok(SqlResult {
-                value: row.get(0).unwrap(),
+            let transaction: i32 = row.get(0)?;
+            let currencies: String = row.get(1)?;
+
+            Ok(Finding {
+                kind: FindingKind::UnbalancedCurrency,
+                transaction: Some(transaction),
+                entry: None,
+                entry_type: None,
+                account: None,
+                message: format!(
+                    "Transaction {} does not balance in {}",
+                    transaction, currencies
+                ),
             })
-        })
-        .and_then(|mapped_rows| {
-            Ok(mapped_rows
-                .map(|row| row.unwrap())
-                .collect::<Vec<SqlResult>>())
-        })?;
+        })?
+        .collect::<rusqlite::Result<Vec<Finding>>>()?;
+    findings.extend(unbalanced);
+
+    // Debits and Credits have their own ids, so every entry is reported with its type
+    let mut stmt = conn.prepare(
+        "SELECT id, 1, transaction_id, account,
+            transaction_id NOT IN (SELECT id FROM Transactions),
+            account NOT IN (SELECT id FROM Accounts)
+        FROM Debits
+        UNION ALL
+        SELECT id, 0, transaction_id, account,
+            transaction_id NOT IN (SELECT id FROM Transactions),
+            account NOT IN (SELECT id FROM Accounts)
+        FROM Credits",
+    )?;
+
+    let broken = stmt
+        .query_map(NO_PARAMS, |row| {
+            let entry: i32 = row.get(0)?;
+            let entry_type: EntryType = row.get(1)?;
+            let transaction: i32 = row.get(2)?;
+            let account: i32 = row.get(3)?;
+            let orphaned: bool = row.get(4)?;
+            let missing_account: bool = row.get(5)?;
+
+            let name = match entry_type {
+                EntryType::Debit => "Debit",
+                EntryType::Credit => "Credit",
+            };
 
-    let result = if query[0].value == 0 { true } else { false };
+            let mut result = Vec::new();
+            if orphaned {
+                result.push(Finding {
+                    kind: FindingKind::OrphanedEntry,
+                    transaction: Some(transaction),
+                    entry: Some(entry),
+                    entry_type: Some(entry_type),
+                    account: Some(account),
+                    message: format!(
+                        "{} {} references the missing transaction {}",
+                        name, entry, transaction
+                    ),
+                });
+            }
+            if missing_account {
+                result.push(Finding {
+                    kind: FindingKind::MissingAccount,
+                    transaction: Some(transaction),
+                    entry: Some(entry),
+                    entry_type: Some(entry_type),
+                    account: Some(account),
+                    message: format!(
+                        "{} {} references the missing account {}",
+                        name, entry, account
+                    ),
+                });
+            }
 
-    Ok(result)
+            Ok(result)
+        })?
+        .collect::<rusqlite::Result<Vec<Vec<Finding>>>>()?;
+    findings.extend(broken.into_iter().flatten());
+
+    Ok(IntegrityReport::new(&totals, findings))
 }
 
-pub fn current_balance(
-    conn: r2d2::PooledConnection<r2d2_sqlite::SqliteConnectionManager>,
-    account: i32,
-) -> Result<SqlResult> {
-    let mut stmt = conn.prepare("SELECT (SELECT ifnull(SUM(balance),0) as \"Debits\" FROM Debits WHERE account = ?1) - (SELECT ifnull(SUM(balance),0) as \"Credits\" FROM Credits WHERE account = ?1)")?;
+pub fn current_balance(conn: &Connection, account: i32) -> Result<SqlResult> {
+    let mut stmt = conn.prepare("SELECT (SELECT ifnull(SUM(balance),0) as \"Debits\" FROM Debits WHERE account = ?1), (SELECT ifnull(SUM(balance),0) as \"Credits\" FROM Credits WHERE account = ?1)")?;
+
+    let (debits, credits) =
+        stmt.query_row(params![account], |row| Ok((row.get(0)?, row.get(1)?)))?;
 
-    stmt.query_row(params![account], |row| {
-        Ok(SqlResult {
-            value: row.get(0).unwrap(),
-        })
+    Ok(SqlResult {
+        value: money::sub(debits, credits)?,
     })
 }
 
-pub fn list_currencies(
-    conn: r2d2::PooledConnection<r2d2_sqlite::SqliteConnectionManager>,
-) -> Result<Vec<Currency>> {
-    let mut stmt = conn.prepare("SELECT code, numeric_code, minor_unit, name FROM Currency")?;
+// Balance of the account counting only transactions dated on or before the date
+pub fn balance_as_of(conn: &Connection, account: i32, date: DateTime<Utc>) -> Result<SqlResult> {
+    let mut stmt = conn.prepare(
+        "SELECT
+        (SELECT ifnull(SUM(d.balance),0) FROM Debits as d INNER JOIN Transactions as t ON d.transaction_id = t.id
+            WHERE d.account = ?1 AND julianday(t.date) <= julianday(?2)),
+        (SELECT ifnull(SUM(c.balance),0) FROM Credits as c INNER JOIN Transactions as t ON c.transaction_id = t.id
+            WHERE c.account = ?1 AND julianday(t.date) <= julianday(?2))",
+    )?;
 
-    let result = stmt
-        .query_map(NO_PARAMS, |row| {
-            Ok(Currency {
-                code: row.get(0).unwrap(),
-                numeric_code: row.get(1).unwrap(),
-                minor_unit: row.get(2).unwrap(),
-                name: row.get(3).unwrap(),
-            })
-        })
-        .and_then(|mapped_rows| {
-            Ok(mapped_rows
-                .map(|row| row.unwrap())
-                .collect::<Vec<Currency>>())
-        })?;
-
-    Ok(result)
+    let (debits, credits) =
+        stmt.query_row(params![account, date], |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+    Ok(SqlResult {
+        value: money::sub(debits, credits)?,
+    })
+}
+
+// Signed movements of the account (debits positive, credits negative)
+// dated after `from` and on or before `to`, in date order
+pub fn account_movements(
+    conn: &Connection,
+    account: i32,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<Vec<(DateTime<Utc>, i64)>> {
+    let mut stmt = conn.prepare(
+        "SELECT t.date, d.balance FROM Debits as d INNER JOIN Transactions as t ON d.transaction_id = t.id
+            WHERE d.account = ?1 AND julianday(t.date) > julianday(?2) AND julianday(t.date) <= julianday(?3)
+        UNION ALL
+        SELECT t.date, -c.balance FROM Credits as c INNER JOIN Transactions as t ON c.transaction_id = t.id
+            WHERE c.account = ?1 AND julianday(t.date) > julianday(?2) AND julianday(t.date) <= julianday(?3)",
+    )?;
+
+    let mut movements = stmt
+        .query_map(params![account, from, to], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?
+        .collect::<rusqlite::Result<Vec<(DateTime<Utc>, i64)>>>()?;
+
+    movements.sort_by_key(|m| m.0);
+
+    Ok(movements)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::migrations;
+    use chrono::TimeZone;
     use r2d2_sqlite::SqliteConnectionManager;
     use rusqlite::params;
 
+    // A migrated ledger that does not enforce foreign keys, so the constraints
+    // don't stop the broken entries an older ledger could contain
+    fn unenforced_pool() -> r2d2::Pool<SqliteConnectionManager> {
+        let manager = SqliteConnectionManager::memory().with_init(|c| {
+            c.execute_batch("PRAGMA foreign_keys = OFF;")?;
+            migrations::migrate(c).map(|_| ())
+        });
+
+        r2d2::Pool::new(manager).unwrap()
+    }
+
+    fn create_ledger(conn: &r2d2::PooledConnection<SqliteConnectionManager>) {
+        conn.execute_batch(
+            "INSERT INTO Accounts (type, name, currency) VALUES (0, 'Current', 'GBP'), (3, 'Salary', 'GBP');",
+        )
+        .unwrap();
+
+        let dates = [
+            Utc.ymd(2020, 1, 10).and_hms(9, 0, 0),
+            Utc.ymd(2020, 2, 10).and_hms(9, 0, 0),
+            Utc.ymd(2020, 3, 10).and_hms(9, 0, 0),
+        ];
+
+        for (i, date) in dates.iter().enumerate() {
+            conn.execute_batch("BEGIN").unwrap();
+            conn.execute(
+                "INSERT INTO Transactions (date, name) VALUES (?1, 'Salary')",
+                params![date],
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO Debits (account, transaction_id, balance) VALUES (1, ?1, 1000)",
+                params![i as i32 + 1],
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO Credits (account, transaction_id, balance) VALUES (2, ?1, 1000)",
+                params![i as i32 + 1],
+            )
+            .unwrap();
+            conn.execute_batch("COMMIT").unwrap();
+        }
+    }
+
     #[test]
-    fn lists_currencies_returns_struct() {
-        let manager = SqliteConnectionManager::memory();
-        let pool = r2d2::Pool::new(manager).unwrap();
-        let conn = pool.get().unwrap();
+    fn balance_as_of_ignores_later_transactions() {
+        let pool = migrations::memory_pool();
+        create_ledger(&pool.get().unwrap());
 
-        let _ = conn.execute(
-            "CREATE TABLE \"Currency\" (
-	        \"code\"	TEXT NOT NULL UNIQUE,
-	        \"numeric_code\"	INTEGER NOT NULL UNIQUE,
-	        \"minor_unit\"	INTEGER NOT NULL DEFAULT 2,
-	        \"name\"	TEXT NOT NULL UNIQUE,
-	        PRIMARY KEY(\"code\")
-            )",
-            params![],
-        );
+        let february = balance_as_of(
+            &pool.get().unwrap(),
+            1,
+            Utc.ymd(2020, 2, 29).and_hms(0, 0, 0),
+        )
+        .unwrap();
+        let revenue = balance_as_of(
+            &pool.get().unwrap(),
+            2,
+            Utc.ymd(2020, 2, 29).and_hms(0, 0, 0),
+        )
+        .unwrap();
 
-        let num = conn.execute(
-            "INSERT INTO Currency (code, numeric_code, minor_unit, name) VALUES ('GBP', '826', '2', 'Pound Sterling');",
-            params![],
-        );
+        assert_eq!(february.value, 2000);
+        assert_eq!(revenue.value, -2000);
+    }
 
-        assert_eq!(num.unwrap(), 1);
+    #[test]
+    fn account_movements_are_signed_and_limited_to_range() {
+        let pool = migrations::memory_pool();
+        create_ledger(&pool.get().unwrap());
 
-        let expected = Currency {
-            code: String::from("GBP"),
-            numeric_code: 826,
-            minor_unit: 2,
-            name: String::from("Pound Sterling"),
-        };
-        let result = list_currencies(conn).unwrap();
+        let movements = account_movements(
+            &pool.get().unwrap(),
+            2,
+            Utc.ymd(2020, 1, 10).and_hms(9, 0, 0),
+            Utc.ymd(2020, 12, 31).and_hms(0, 0, 0),
+        )
+        .unwrap();
 
-        assert_eq!(expected, result[0]);
+        assert_eq!(
+            movements,
+            vec![
+                (Utc.ymd(2020, 2, 10).and_hms(9, 0, 0), -1000),
+                (Utc.ymd(2020, 3, 10).and_hms(9, 0, 0), -1000),
+            ]
+        );
     }
 
     #[test]
-    fn list_currencies_can_return_multiple_currencies() {
-        let manager = SqliteConnectionManager::memory();
-        let pool = r2d2::Pool::new(manager).unwrap();
+    fn integrity_report_finds_broken_transactions_and_entries() {
+        let pool = unenforced_pool();
         let conn = pool.get().unwrap();
+        create_ledger(&conn);
 
-        let _ = conn.execute(
-            "CREATE TABLE \"Currency\" (
-	        \"code\"	TEXT NOT NULL UNIQUE,
-	        \"numeric_code\"	INTEGER NOT NULL UNIQUE,
-	        \"minor_unit\"	INTEGER NOT NULL DEFAULT 2,
-	        \"name\"	TEXT NOT NULL UNIQUE,
-	        PRIMARY KEY(\"code\")
-            )",
-            params![],
-        );
+        conn.execute_batch(
+            "INSERT INTO Accounts (type, name, currency) VALUES (0, 'Euro', 'EUR');
 
-        let num = conn.execute(
-            "INSERT INTO Currency (code, numeric_code, minor_unit, name) VALUES 
-            ('GBP', '826', '2', 'Pound Sterling'),
-            ('EUR', '978', '2', 'Euro'),
-            ('PLN', '985', '2', 'Zloty');",
-            params![],
+            INSERT INTO Transactions (date, name) VALUES
+                ('2020-04-10T09:00:00+00:00', 'Short'),
+                ('2020-04-11T09:00:00+00:00', 'Nothing'),
+                ('2020-04-12T09:00:00+00:00', 'Holiday');
+            INSERT INTO Debits (account, transaction_id, balance) VALUES (1, 4, 1000), (3, 6, 500), (1, 99, 10);
+            INSERT INTO Credits (account, transaction_id, balance) VALUES (2, 4, 900), (1, 6, 500), (42, 1, 5);",
+        )
+        .unwrap();
+
+        let report = check_integrity(&conn).unwrap();
+        let kinds: Vec<(FindingKind, Option<i32>)> = report
+            .findings
+            .iter()
+            .map(|f| (f.kind, f.transaction))
+            .collect();
+
+        assert!(!report.integrity);
+        assert_eq!(report.transactions, 6);
+        assert_eq!(
+            kinds,
+            vec![
+                (FindingKind::Unbalanced, Some(1)),
+                (FindingKind::Unbalanced, Some(4)),
+                (FindingKind::Empty, Some(5)),
+                (FindingKind::UnbalancedCurrency, Some(6)),
+                (FindingKind::OrphanedEntry, Some(99)),
+                (FindingKind::MissingAccount, Some(1)),
+            ]
+        );
+        assert_eq!(
+            report.findings[1].message,
+            "Transaction 4 has 1000 in debits and 900 in credits"
         );
-        assert_eq!(num.unwrap(), 3);
+        assert_eq!(
+            report.findings[3].message,
+            "Transaction 6 does not balance in EUR,GBP"
+        );
+    }
+
+    #[test]
+    fn balanced_ledger_has_integrity() {
+        let pool = migrations::memory_pool();
+        let conn = pool.get().unwrap();
+        create_ledger(&conn);
 
-        let result = list_currencies(conn).unwrap();
+        let report = check_integrity(&conn).unwrap();
 
-        assert_eq!(result.len(), 3)
+        assert!(report.integrity);
+        assert_eq!(report.transactions, 3);
+        assert!(report.findings.is_empty());
     }
 }
@@ -0,0 +1,191 @@
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use serde_derive::Serialize;
+use std::fmt;
+
+pub type Result<T> = std::result::Result<T, LedgerError>;
+
+#[derive(Debug)]
+pub enum LedgerError {
+    // The requested record does not exist
+    NotFound(String),
+    // The request itself is invalid
+    Validation(String),
+    // The request clashes with the current state of the ledger
+    Conflict(String),
+    // The stored ledger is inconsistent, e.g. an unknown account type
+    Integrity(String),
+    // An amount or a total does not fit into 64 bits
+    Overflow(String),
+    Storage(rusqlite::Error),
+    PoolExhausted(r2d2::Error),
+}
+
+// The body returned with every error response
+#[derive(Debug, Serialize)]
+pub struct Problem {
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl LedgerError {
+    pub fn not_found(record: &str, id: impl fmt::Display) -> LedgerError {
+        LedgerError::NotFound(format!("{} {} not found", record, id))
+    }
+
+    pub fn overflow(what: impl fmt::Display) -> LedgerError {
+        LedgerError::Overflow(format!("{} is too large to be stored", what))
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            LedgerError::NotFound(_) => "not_found",
+            LedgerError::Validation(_) => "validation",
+            LedgerError::Conflict(_) => "conflict",
+            LedgerError::Integrity(_) => "integrity",
+            LedgerError::Overflow(_) => "overflow",
+            LedgerError::Storage(_) => "storage",
+            LedgerError::PoolExhausted(_) => "pool_exhausted",
+        }
+    }
+}
+
+// Names the missing record if a query for it returned no rows
+pub fn missing(error: rusqlite::Error, record: &str, id: impl fmt::Display) -> LedgerError {
+    match error {
+        rusqlite::Error::QueryReturnedNoRows => LedgerError::not_found(record, id),
+        e => LedgerError::from(e),
+    }
+}
+
+impl fmt::Display for LedgerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LedgerError::NotFound(m)
+            | LedgerError::Validation(m)
+            | LedgerError::Conflict(m)
+            | LedgerError::Integrity(m)
+            | LedgerError::Overflow(m) => write!(f, "{}", m),
+            LedgerError::Storage(e) => write!(f, "Storage error: {}", e),
+            LedgerError::PoolExhausted(e) => write!(f, "No database connection available: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for LedgerError {}
+
+impl From<rusqlite::Error> for LedgerError {
+    fn from(error: rusqlite::Error) -> LedgerError {
+        match error {
+            rusqlite::Error::QueryReturnedNoRows => {
+                LedgerError::NotFound(String::from("Record not found"))
+            }
+            // Stored values that can't be decoded, e.g. an unknown account type
+            rusqlite::Error::IntegralValueOutOfRange(column, value) => LedgerError::Integrity(
+                format!("Unknown value {} stored in column {}", value, column),
+            ),
+            rusqlite::Error::FromSqlConversionFailure(column, _, e) => {
+                LedgerError::Integrity(format!("Invalid value stored in column {}: {}", column, e))
+            }
+            // SUM raises an error instead of wrapping around
+            rusqlite::Error::SqliteFailure(_, Some(message)) if message == "integer overflow" => {
+                LedgerError::Overflow(String::from("A total is too large to be stored"))
+            }
+            rusqlite::Error::SqliteFailure(e, message)
+                if e.code == rusqlite::ErrorCode::ConstraintViolation =>
+            {
+                LedgerError::Conflict(
+                    message.unwrap_or_else(|| String::from("Constraint violation")),
+                )
+            }
+            e => LedgerError::Storage(e),
+        }
+    }
+}
+
+impl From<r2d2::Error> for LedgerError {
+    fn from(error: r2d2::Error) -> LedgerError {
+        LedgerError::PoolExhausted(error)
+    }
+}
+
+impl ResponseError for LedgerError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            LedgerError::NotFound(_) => StatusCode::NOT_FOUND,
+            LedgerError::Validation(_) => StatusCode::BAD_REQUEST,
+            LedgerError::Conflict(_) => StatusCode::CONFLICT,
+            LedgerError::Overflow(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            LedgerError::Integrity(_) | LedgerError::Storage(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+            LedgerError::PoolExhausted(_) => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        if self.status_code().is_server_error() {
+            error!("Request failed with {error}", error = self);
+        }
+
+        HttpResponse::build(self.status_code()).json(Problem {
+            code: self.code(),
+            message: self.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sqlite_errors_are_mapped() {
+        let missing = LedgerError::from(rusqlite::Error::QueryReturnedNoRows);
+        let corrupt = LedgerError::from(rusqlite::Error::IntegralValueOutOfRange(1, 9));
+        let other = LedgerError::from(rusqlite::Error::InvalidQuery);
+
+        assert_eq!(missing.code(), "not_found");
+        assert_eq!(missing.status_code(), StatusCode::NOT_FOUND);
+        assert_eq!(corrupt.code(), "integrity");
+        assert_eq!(corrupt.to_string(), "Unknown value 9 stored in column 1");
+        assert_eq!(other.code(), "storage");
+        assert_eq!(other.status_code(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[test]
+    fn overflowing_totals_are_not_storage_errors() {
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        let error = conn
+            .query_row(
+                "SELECT SUM(v) FROM (SELECT 9223372036854775807 as v UNION ALL SELECT 1)",
+                rusqlite::NO_PARAMS,
+                |row| row.get::<_, i64>(0),
+            )
+            .map_err(LedgerError::from)
+            .unwrap_err();
+
+        assert_eq!(error.code(), "overflow");
+        assert_eq!(error.status_code(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[test]
+    fn validation_is_a_bad_request() {
+        let error = LedgerError::Validation(String::from("Unknown account type: 9"));
+
+        assert_eq!(error.status_code(), StatusCode::BAD_REQUEST);
+        assert_eq!(error.to_string(), "Unknown account type: 9");
+    }
+
+    #[test]
+    fn not_found_names_the_record() {
+        let error = LedgerError::not_found("Account", 3);
+
+        assert_eq!(error.to_string(), "Account 3 not found");
+        assert_eq!(error.code(), "not_found");
+        assert_eq!(
+            missing(rusqlite::Error::QueryReturnedNoRows, "Budget", 2).to_string(),
+            "Budget 2 not found"
+        );
+    }
+}
@@ -0,0 +1,141 @@
+use chrono::{DateTime, Utc};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::currency::data::valid_code;
+use crate::datastruct::parse_date;
+
+// 1 base = rate quote on the date, e.g. GBP/EUR at 1.15
+#[derive(Debug, Serialize, PartialEq)]
+pub struct ExchangeRate {
+    pub id: i32,
+    pub base: String,
+    pub quote: String,
+    pub date: DateTime<Utc>,
+    pub rate: f64,
+    pub source: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NewExchangeRate {
+    pub base: String,
+    pub quote: String,
+    pub date: String,
+    pub rate: f64,
+    pub source: Option<String>,
+}
+
+impl NewExchangeRate {
+    // Describes the first invalid field, if there is one
+    pub fn check(&self) -> Result<(), String> {
+        if !valid_code(&self.base) || !valid_code(&self.quote) {
+            return Err(String::from(
+                "base and quote have to be three upper case letters, e.g. GBP",
+            ));
+        }
+
+        if self.base == self.quote {
+            return Err(String::from(
+                "base and quote have to be different currencies",
+            ));
+        }
+
+        if parse_date(&self.date).is_none() {
+            return Err(String::from("date has to be an RFC 3339 timestamp"));
+        }
+
+        if !self.rate.is_finite() || self.rate <= 0.0 {
+            return Err(String::from("rate has to be a positive number"));
+        }
+
+        Ok(())
+    }
+
+    // Only meaningful once the rate has been checked
+    pub fn date(&self) -> DateTime<Utc> {
+        parse_date(&self.date).unwrap_or_else(Utc::now)
+    }
+}
+
+// Filters the listed rates by currency pair
+#[derive(Debug, Deserialize)]
+pub struct RateQuery {
+    base: Option<String>,
+    quote: Option<String>,
+}
+
+impl RateQuery {
+    pub fn base(&self) -> Option<&str> {
+        self.base.as_deref()
+    }
+
+    pub fn quote(&self) -> Option<&str> {
+        self.quote.as_deref()
+    }
+}
+
+// The trading account that takes the other side of the legs in a currency
+#[derive(Debug, Serialize, PartialEq)]
+pub struct FxAccount {
+    pub currency: String,
+    pub account: i32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetFxAccount {
+    pub account: i32,
+}
+
+// Converts an amount in minor units of one currency into minor units of
+// another, where 1 of the first currency is worth rate of the second.
+// None if the converted amount does not fit into 64 bits
+pub fn convert(amount: i64, rate: f64, from_minor_unit: i32, to_minor_unit: i32) -> Option<i64> {
+    let scale = 10f64.powi(to_minor_unit - from_minor_unit);
+    let converted = (amount as f64 * rate * scale).round();
+
+    // i64::MAX as f64 rounds up to 2^63, which is already out of range
+    if !converted.is_finite() || converted.abs() >= i64::MAX as f64 {
+        return None;
+    }
+
+    Some(converted as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rate(base: &str, quote: &str, rate: f64) -> NewExchangeRate {
+        NewExchangeRate {
+            base: String::from(base),
+            quote: String::from(quote),
+            date: String::from("2020-01-15T00:00:00+00:00"),
+            rate,
+            source: None,
+        }
+    }
+
+    #[test]
+    fn rates_need_two_currencies_and_a_positive_rate() {
+        assert!(rate("GBP", "EUR", 1.15).check().is_ok());
+        assert!(rate("GBP", "GBP", 1.0).check().is_err());
+        assert!(rate("GBP", "eur", 1.15).check().is_err());
+        assert!(rate("GBP", "EUR", 0.0).check().is_err());
+        assert!(rate("GBP", "EUR", f64::NAN).check().is_err());
+
+        let mut undated = rate("GBP", "EUR", 1.15);
+        undated.date = String::from("15/01/2020");
+        assert!(undated.check().is_err());
+    }
+
+    #[test]
+    fn conversion_rounds_to_the_minor_unit_of_the_target() {
+        // 100.00 GBP at 1.15 is 115.00 EUR
+        assert_eq!(convert(10000, 1.15, 2, 2), Some(11500));
+        // 10.00 GBP at 137.456 is 1375 JPY, which has no minor unit
+        assert_eq!(convert(1000, 137.456, 2, 0), Some(1375));
+        // 1375 JPY back at 0.00727 is 10.00 GBP
+        assert_eq!(convert(1375, 0.00727, 0, 2), Some(1000));
+        assert_eq!(convert(-10000, 1.15, 2, 2), Some(-11500));
+        assert_eq!(convert(i64::MAX / 2, 3.0, 2, 2), None);
+    }
+}
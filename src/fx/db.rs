@@ -0,0 +1,344 @@
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension, Row, NO_PARAMS};
+use std::collections::HashSet;
+
+use crate::error::{missing, LedgerError, Result};
+use crate::fx::data::{ExchangeRate, FxAccount, NewExchangeRate};
+
+fn rate_from_row(row: &Row) -> rusqlite::Result<ExchangeRate> {
+    Ok(ExchangeRate {
+        id: row.get(0)?,
+        base: row.get(1)?,
+        quote: row.get(2)?,
+        date: row.get(3)?,
+        rate: row.get(4)?,
+        source: row.get(5)?,
+    })
+}
+
+pub fn get_rate(conn: &Connection, id: i32) -> Result<ExchangeRate> {
+    conn.query_row(
+        "SELECT id, base, quote, date, rate, source FROM ExchangeRates WHERE id = ?1",
+        params![id],
+        rate_from_row,
+    )
+    .map_err(|e| missing(e, "Exchange rate", id))
+}
+
+// Every rate, optionally only for one base and/or quote currency
+pub fn list_rates(
+    conn: &Connection,
+    base: Option<&str>,
+    quote: Option<&str>,
+) -> Result<Vec<ExchangeRate>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, base, quote, date, rate, source FROM ExchangeRates
+        WHERE (?1 IS NULL OR base = ?1) AND (?2 IS NULL OR quote = ?2)
+        ORDER BY base, quote, date",
+    )?;
+
+    let result = stmt
+        .query_map(params![base, quote], rate_from_row)?
+        .collect::<rusqlite::Result<Vec<ExchangeRate>>>()?;
+
+    Ok(result)
+}
+
+pub fn add_rate(conn: &mut Connection, rate: &NewExchangeRate) -> Result<i32> {
+    let tx = conn.transaction()?;
+
+    tx.execute(
+        "INSERT INTO ExchangeRates (base, quote, date, rate, source) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![rate.base, rate.quote, rate.date(), rate.rate, rate.source],
+    )?;
+
+    let id = tx.last_insert_rowid() as i32;
+
+    tx.commit()?;
+
+    Ok(id)
+}
+
+pub fn update_rate(conn: &mut Connection, id: i32, rate: &NewExchangeRate) -> Result<()> {
+    let tx = conn.transaction()?;
+
+    let updated = tx.execute(
+        "UPDATE ExchangeRates SET base = ?1, quote = ?2, date = ?3, rate = ?4, source = ?5
+        WHERE id = ?6",
+        params![
+            rate.base,
+            rate.quote,
+            rate.date(),
+            rate.rate,
+            rate.source,
+            id
+        ],
+    )?;
+
+    if updated == 0 {
+        return Err(LedgerError::not_found("Exchange rate", id));
+    }
+
+    tx.commit()?;
+
+    Ok(())
+}
+
+pub fn remove_rate(conn: &mut Connection, id: i32) -> Result<()> {
+    let tx = conn.transaction()?;
+
+    let deleted = tx.execute("DELETE FROM ExchangeRates WHERE id = ?1", params![id])?;
+
+    if deleted == 0 {
+        return Err(LedgerError::not_found("Exchange rate", id));
+    }
+
+    tx.commit()?;
+
+    Ok(())
+}
+
+// Stores all of the rates or none of them. A rate for a pair and date that
+// is already stored replaces the stored one
+pub fn import_rates(conn: &mut Connection, rates: &[NewExchangeRate]) -> Result<u32> {
+    let tx = conn.transaction()?;
+
+    // Rows of the same pair and date replace each other and are stored once
+    let mut stored = HashSet::new();
+
+    for rate in rates {
+        stored.insert((&rate.base, &rate.quote, rate.date()));
+
+        tx.execute(
+            "INSERT INTO ExchangeRates (base, quote, date, rate, source) VALUES (?1, ?2, ?3, ?4, ?5)
+            ON CONFLICT(base, quote, date) DO UPDATE SET rate = excluded.rate, source = excluded.source",
+            params![rate.base, rate.quote, rate.date(), rate.rate, rate.source],
+        )?;
+    }
+
+    tx.commit()?;
+
+    Ok(stored.len() as u32)
+}
+
+// The most recent rate on or before the date that converts from into to.
+// A rate stored the other way around is inverted. When both pairs have a rate
+// for the same date the direct one is used
+pub fn rate_as_of(
+    conn: &Connection,
+    from: &str,
+    to: &str,
+    date: DateTime<Utc>,
+) -> Result<Option<f64>> {
+    let rate = conn
+        .query_row(
+            "SELECT rate FROM (
+                SELECT date, rate, 1 as direct FROM ExchangeRates WHERE base = ?1 AND quote = ?2 AND date <= ?3
+                UNION ALL
+                SELECT date, 1.0 / rate, 0 as direct FROM ExchangeRates WHERE base = ?2 AND quote = ?1 AND date <= ?3
+            ) ORDER BY date DESC, direct DESC LIMIT 1",
+            params![from, to, date],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    Ok(rate)
+}
+
+pub fn list_fx_accounts(conn: &Connection) -> Result<Vec<FxAccount>> {
+    let mut stmt = conn.prepare("SELECT currency, account FROM FxAccounts ORDER BY currency")?;
+
+    let result = stmt
+        .query_map(NO_PARAMS, |row| {
+            Ok(FxAccount {
+                currency: row.get(0)?,
+                account: row.get(1)?,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<FxAccount>>>()?;
+
+    Ok(result)
+}
+
+// The FX account of a currency, if one is configured
+pub fn get_fx_account(conn: &Connection, currency: &str) -> Result<Option<i32>> {
+    let account = conn
+        .query_row(
+            "SELECT account FROM FxAccounts WHERE currency = ?1",
+            params![currency],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    Ok(account)
+}
+
+// Replaces the FX account of the currency if it already has one
+pub fn set_fx_account(conn: &mut Connection, currency: &str, account: i32) -> Result<()> {
+    let tx = conn.transaction()?;
+
+    tx.execute(
+        "INSERT INTO FxAccounts (currency, account) VALUES (?1, ?2)
+        ON CONFLICT(currency) DO UPDATE SET account = excluded.account",
+        params![currency, account],
+    )?;
+
+    tx.commit()?;
+
+    Ok(())
+}
+
+pub fn remove_fx_account(conn: &mut Connection, currency: &str) -> Result<()> {
+    let tx = conn.transaction()?;
+
+    let deleted = tx.execute(
+        "DELETE FROM FxAccounts WHERE currency = ?1",
+        params![currency],
+    )?;
+
+    if deleted == 0 {
+        return Err(LedgerError::not_found("FX account for", currency));
+    }
+
+    tx.commit()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations;
+    use chrono::TimeZone;
+
+    fn new_rate(base: &str, quote: &str, date: &str, rate: f64) -> NewExchangeRate {
+        NewExchangeRate {
+            base: String::from(base),
+            quote: String::from(quote),
+            date: String::from(date),
+            rate,
+            source: Some(String::from("ECB")),
+        }
+    }
+
+    #[test]
+    fn rates_can_be_added_updated_and_removed() {
+        let pool = migrations::memory_pool();
+        let mut conn = pool.get().unwrap();
+
+        let id = add_rate(
+            &mut conn,
+            &new_rate("GBP", "EUR", "2020-01-15T00:00:00+00:00", 1.15),
+        )
+        .unwrap();
+
+        let stored = get_rate(&conn, id).unwrap();
+        assert_eq!(stored.date, Utc.ymd(2020, 1, 15).and_hms(0, 0, 0));
+        assert_eq!(stored.rate, 1.15);
+        assert_eq!(stored.source.as_deref(), Some("ECB"));
+
+        update_rate(
+            &mut conn,
+            id,
+            &new_rate("GBP", "EUR", "2020-01-15T00:00:00+00:00", 1.17),
+        )
+        .unwrap();
+        assert_eq!(get_rate(&conn, id).unwrap().rate, 1.17);
+
+        remove_rate(&mut conn, id).unwrap();
+        match get_rate(&conn, id) {
+            Err(LedgerError::NotFound(m)) => {
+                assert_eq!(m, format!("Exchange rate {} not found", id))
+            }
+            other => panic!("Expected not found, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn import_replaces_rates_of_the_same_pair_and_date() {
+        let pool = migrations::memory_pool();
+        let mut conn = pool.get().unwrap();
+
+        let rates = vec![
+            new_rate("GBP", "EUR", "2020-01-15T00:00:00+00:00", 1.15),
+            new_rate("GBP", "EUR", "2020-01-16T00:00:00+00:00", 1.16),
+            new_rate("GBP", "USD", "2020-01-15T00:00:00+00:00", 1.30),
+        ];
+        assert_eq!(import_rates(&mut conn, &rates).unwrap(), 3);
+
+        let corrected = vec![
+            new_rate("GBP", "EUR", "2020-01-16T00:00:00+00:00", 1.17),
+            new_rate("GBP", "EUR", "2020-01-16T00:00:00+00:00", 1.18),
+        ];
+        assert_eq!(import_rates(&mut conn, &corrected).unwrap(), 1);
+
+        let euros = list_rates(&conn, Some("GBP"), Some("EUR")).unwrap();
+        assert_eq!(euros.len(), 2);
+        assert_eq!(euros[1].rate, 1.18);
+        assert_eq!(list_rates(&conn, None, None).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn rates_are_looked_up_as_of_a_date_in_both_directions() {
+        let pool = migrations::memory_pool();
+        let mut conn = pool.get().unwrap();
+
+        let rates = vec![
+            new_rate("GBP", "EUR", "2020-01-10T00:00:00+00:00", 1.10),
+            new_rate("EUR", "GBP", "2020-01-20T00:00:00+00:00", 0.8),
+        ];
+        import_rates(&mut conn, &rates).unwrap();
+
+        let before = Utc.ymd(2020, 1, 1).and_hms(0, 0, 0);
+        let middle = Utc.ymd(2020, 1, 15).and_hms(0, 0, 0);
+        let after = Utc.ymd(2020, 2, 1).and_hms(0, 0, 0);
+
+        assert_eq!(rate_as_of(&conn, "GBP", "EUR", before).unwrap(), None);
+        assert_eq!(rate_as_of(&conn, "GBP", "EUR", middle).unwrap(), Some(1.10));
+        assert_eq!(rate_as_of(&conn, "GBP", "EUR", after).unwrap(), Some(1.25));
+        assert_eq!(rate_as_of(&conn, "EUR", "GBP", after).unwrap(), Some(0.8));
+        assert_eq!(rate_as_of(&conn, "GBP", "USD", after).unwrap(), None);
+    }
+
+    #[test]
+    fn the_direct_pair_wins_over_an_inverted_rate_of_the_same_date() {
+        let pool = migrations::memory_pool();
+        let mut conn = pool.get().unwrap();
+
+        let rates = vec![
+            new_rate("EUR", "GBP", "2020-01-10T00:00:00+00:00", 0.8),
+            new_rate("GBP", "EUR", "2020-01-10T00:00:00+00:00", 1.10),
+        ];
+        import_rates(&mut conn, &rates).unwrap();
+
+        let date = Utc.ymd(2020, 1, 15).and_hms(0, 0, 0);
+
+        assert_eq!(rate_as_of(&conn, "GBP", "EUR", date).unwrap(), Some(1.10));
+        assert_eq!(rate_as_of(&conn, "EUR", "GBP", date).unwrap(), Some(0.8));
+    }
+
+    #[test]
+    fn every_currency_has_at_most_one_fx_account() {
+        let pool = migrations::memory_pool();
+        let mut conn = pool.get().unwrap();
+
+        conn.execute_batch(
+            "INSERT INTO Accounts (type, name, currency) VALUES (2, 'FX Sterling', 'GBP');
+            INSERT INTO Accounts (type, name, currency) VALUES (2, 'FX Sterling 2', 'GBP');",
+        )
+        .unwrap();
+
+        set_fx_account(&mut conn, "GBP", 1).unwrap();
+        set_fx_account(&mut conn, "GBP", 2).unwrap();
+
+        assert_eq!(get_fx_account(&conn, "GBP").unwrap(), Some(2));
+        assert_eq!(get_fx_account(&conn, "EUR").unwrap(), None);
+        assert_eq!(list_fx_accounts(&conn).unwrap().len(), 1);
+
+        remove_fx_account(&mut conn, "GBP").unwrap();
+        match remove_fx_account(&mut conn, "GBP") {
+            Err(LedgerError::NotFound(_)) => {}
+            other => panic!("Expected not found, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use actix_web::{web, HttpResponse};
+use serde_json::json;
+
+pub mod data;
+pub mod db;
+
+use crate::datastruct;
+use crate::error::Result;
+use crate::Ledger;
+
+pub async fn list_exchange_rates(
+    query: web::Query<data::RateQuery>,
+    ledger: web::Data<Ledger>,
+) -> Result<HttpResponse> {
+    let result = ledger.exchange_rates(query.base(), query.quote())?;
+
+    Ok(HttpResponse::Ok().json(result))
+}
+
+pub async fn get_exchange_rate(
+    params: web::Path<datastruct::IdRequest>,
+    ledger: web::Data<Ledger>,
+) -> Result<HttpResponse> {
+    let result = ledger.exchange_rate(params.id)?;
+
+    Ok(HttpResponse::Ok().json(result))
+}
+
+pub async fn create_exchange_rate(
+    rate: web::Json<data::NewExchangeRate>,
+    ledger: web::Data<Ledger>,
+) -> Result<HttpResponse> {
+    let result = ledger.create_exchange_rate(&rate)?;
+
+    Ok(HttpResponse::Created().json(result))
+}
+
+pub async fn update_exchange_rate(
+    params: web::Path<datastruct::IdRequest>,
+    rate: web::Json<data::NewExchangeRate>,
+    ledger: web::Data<Ledger>,
+) -> Result<HttpResponse> {
+    let result = ledger.update_exchange_rate(params.id, &rate)?;
+
+    Ok(HttpResponse::Ok().json(result))
+}
+
+pub async fn delete_exchange_rate(
+    params: web::Path<datastruct::IdRequest>,
+    ledger: web::Data<Ledger>,
+) -> Result<HttpResponse> {
+    ledger.delete_exchange_rate(params.id)?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+// Stores a list of rates in one go, replacing rates for the same pair and date
+pub async fn import_exchange_rates(
+    rates: web::Json<Vec<data::NewExchangeRate>>,
+    ledger: web::Data<Ledger>,
+) -> Result<HttpResponse> {
+    let imported = ledger.import_exchange_rates(&rates)?;
+
+    Ok(HttpResponse::Ok().json(json!({ "imported": imported })))
+}
+
+pub async fn list_fx_accounts(ledger: web::Data<Ledger>) -> Result<HttpResponse> {
+    let result = ledger.fx_accounts()?;
+
+    Ok(HttpResponse::Ok().json(result))
+}
+
+pub async fn set_fx_account(
+    params: web::Path<datastruct::CodeRequest>,
+    fx_account: web::Json<data::SetFxAccount>,
+    ledger: web::Data<Ledger>,
+) -> Result<HttpResponse> {
+    let result = ledger.set_fx_account(&params.code, fx_account.account)?;
+
+    Ok(HttpResponse::Ok().json(result))
+}
+
+pub async fn delete_fx_account(
+    params: web::Path<datastruct::CodeRequest>,
+    ledger: web::Data<Ledger>,
+) -> Result<HttpResponse> {
+    ledger.delete_fx_account(&params.code)?;
+
+    Ok(HttpResponse::Ok().finish())
+}
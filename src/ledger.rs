@@ -0,0 +1,1238 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+
+use chrono::{DateTime, Duration, Utc};
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::Connection;
+
+use crate::account::data::{
+    Account, AccountBalance, AccountNode, AccountQuery, AccountRegister, AccountType,
+    BalanceHistory, DetailedAccount, Interval, NewAccount, RegisterQuery, UpdateAccount,
+};
+use crate::budget::data::{Budget, BudgetEntry, NewBudget, NewBudgetEntry};
+use crate::currency::data::{Currency, UpdateCurrency};
+use crate::datastruct::IntegrityReport;
+use crate::error::{LedgerError, Result};
+use crate::fx::data::{ExchangeRate, FxAccount, NewExchangeRate};
+use crate::money::{self, Amount, Money};
+use crate::report::data::{BalanceSheet, IncomeStatement, NetWorth, TrialBalance};
+use crate::transaction::data::{
+    legs_balanced, legs_positive, NewEntry, NewTransaction, Posting, Transaction, TransactionV2,
+    UpdateTransaction,
+};
+use crate::{account, budget, currency, db, fx, migrations, report, transaction};
+
+// Where the database connections of a ledger come from
+enum Source {
+    Pool(Pool<SqliteConnectionManager>),
+    Connection(Mutex<Connection>),
+}
+
+// The ledger with all of its checks, for the server, pig and anything that
+// wants to book transactions in process. Every method takes a connection
+// for its own duration, so a ledger can be shared between threads
+pub struct Ledger {
+    source: Source,
+}
+
+impl Ledger {
+    // Opens the database at path, creating it if needed
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Ledger> {
+        let manager = SqliteConnectionManager::file(path).with_init(db::init_connection);
+
+        Ledger::from_pool(Pool::new(manager)?)
+    }
+
+    // A ledger that only lives as long as the value, e.g. for tests
+    pub fn open_in_memory() -> Result<Ledger> {
+        Ledger::from_connection(Connection::open_in_memory()?)
+    }
+
+    // Both constructors bring the schema up to date before handing out the ledger
+    pub fn from_pool(pool: Pool<SqliteConnectionManager>) -> Result<Ledger> {
+        let ledger = Ledger {
+            source: Source::Pool(pool),
+        };
+        ledger.with_connection(|conn| Ok(migrations::migrate(conn)?))?;
+
+        Ok(ledger)
+    }
+
+    pub fn from_connection(conn: Connection) -> Result<Ledger> {
+        db::init_connection(&conn)?;
+        migrations::migrate(&conn)?;
+
+        Ok(Ledger {
+            source: Source::Connection(Mutex::new(conn)),
+        })
+    }
+
+    fn with_connection<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Connection) -> Result<T>,
+    {
+        match &self.source {
+            Source::Pool(pool) => {
+                let mut conn = pool.get()?;
+                // The deferred ledger constraints rely on foreign keys, which
+                // a pool built elsewhere may not have switched on
+                db::init_connection(&conn)?;
+                f(&mut conn)
+            }
+            Source::Connection(conn) => {
+                // A panic half way through rolled its database transaction back
+                let mut conn = conn.lock().unwrap_or_else(PoisonError::into_inner);
+                f(&mut conn)
+            }
+        }
+    }
+
+    pub fn schema_version(&self) -> Result<u32> {
+        self.with_connection(|conn| Ok(migrations::current_version(conn)?))
+    }
+
+    // Accounts
+
+    pub fn account(&self, id: i32) -> Result<Account> {
+        self.with_connection(|conn| account::db::get_account(conn, id))
+    }
+
+    pub fn accounts(&self, include_archived: bool) -> Result<Vec<Account>> {
+        self.with_connection(|conn| account::db::list_accounts(conn, include_archived))
+    }
+
+    pub fn accounts_of_type(&self, account_type: AccountType) -> Result<Vec<DetailedAccount>> {
+        self.with_connection(|conn| account::db::list_accounts_filter_type(conn, account_type))
+    }
+
+    // Accounts with balances, filtered by type and name
+    pub fn search_accounts(&self, query: &AccountQuery) -> Result<Vec<DetailedAccount>> {
+        query.types().map_err(LedgerError::Validation)?;
+
+        let accounts = self.with_connection(|conn| {
+            account::db::list_detailed_accounts(conn, query.include_archived())
+        })?;
+
+        Ok(query.apply(accounts))
+    }
+
+    // The chart of accounts with rolled up balances
+    pub fn account_tree(&self) -> Result<Vec<AccountNode>> {
+        let accounts =
+            self.with_connection(|conn| account::db::list_detailed_accounts(conn, true))?;
+
+        AccountNode::tree(accounts)
+    }
+
+    // A child account has to share the type and currency of an open parent
+    pub fn create_account(&self, new: &NewAccount) -> Result<Account> {
+        let account_type = new
+            .acc_type
+            .account_type()
+            .map_err(LedgerError::Validation)?;
+
+        self.with_connection(|conn| {
+            if let Some(parent_id) = new.parent {
+                let parent = match account::db::get_account(conn, parent_id) {
+                    Ok(v) => v,
+                    Err(LedgerError::NotFound(_)) => {
+                        return Err(LedgerError::Validation(format!(
+                            "Parent account {} does not exist",
+                            parent_id
+                        )))
+                    }
+                    Err(e) => return Err(e),
+                };
+
+                if parent.archived {
+                    return Err(LedgerError::Validation(format!(
+                        "Parent account {} is archived",
+                        parent_id
+                    )));
+                }
+
+                if !parent.can_parent(account_type, &new.currency) {
+                    return Err(LedgerError::Validation(String::from(
+                        "A child account must have the same type and currency as its parent",
+                    )));
+                }
+            }
+
+            let id = account::db::add_account(
+                conn,
+                account_type,
+                &new.name,
+                &new.currency,
+                new.parent,
+                new.description.as_deref(),
+            )?;
+
+            account::db::get_account(conn, id)
+        })
+    }
+
+    pub fn update_account(&self, id: i32, update: &UpdateAccount) -> Result<Account> {
+        if !update.valid() {
+            return Err(LedgerError::Validation(String::from(
+                "Account name can not be empty",
+            )));
+        }
+
+        self.with_connection(|conn| {
+            account::db::update_account(conn, id, &update.name, update.description.as_deref())?;
+
+            account::db::get_account(conn, id)
+        })
+    }
+
+    // Archived accounts are hidden from listings and can't receive new entries
+    pub fn archive_account(&self, id: i32) -> Result<()> {
+        self.with_connection(|conn| account::db::set_account_archived(conn, id, true))
+    }
+
+    pub fn reopen_account(&self, id: i32) -> Result<()> {
+        self.with_connection(|conn| account::db::set_account_archived(conn, id, false))
+    }
+
+    // Only accounts that nothing references can be deleted.
+    // Accounts with history should be archived instead
+    pub fn delete_account(&self, id: i32) -> Result<()> {
+        self.with_connection(|conn| {
+            if account::db::account_in_use(conn, id)? {
+                return Err(LedgerError::Conflict(format!(
+                    "Account {} is still in use and can only be archived",
+                    id
+                )));
+            }
+
+            account::db::remove_account(conn, id)
+        })
+    }
+
+    // A page of the entries of an account with a running balance
+    pub fn register(
+        &self,
+        id: i32,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+        limit: u32,
+        offset: u32,
+    ) -> Result<AccountRegister> {
+        if from.is_some() && to.is_some() && from > to {
+            return Err(LedgerError::Validation(String::from(
+                "from has to be before to",
+            )));
+        }
+
+        if limit == 0 || limit > RegisterQuery::MAX_LIMIT {
+            return Err(LedgerError::Validation(format!(
+                "limit has to be between 1 and {}",
+                RegisterQuery::MAX_LIMIT
+            )));
+        }
+
+        self.with_connection(|conn| {
+            let account = account::db::get_account(conn, id)?;
+            let entries = account::db::list_account_entries(conn, id, from, to, limit, offset)?;
+
+            Ok(AccountRegister {
+                id: account.id,
+                currency: account.currency,
+                limit,
+                offset,
+                entries,
+            })
+        })
+    }
+
+    // Without a date every transaction counts, including future dated ones
+    pub fn balance(&self, id: i32, date: Option<DateTime<Utc>>) -> Result<AccountBalance> {
+        self.with_connection(|conn| {
+            let account = account::db::get_account(conn, id)?;
+
+            let currency = currency::db::get_currency(conn, &account.currency)?;
+
+            let balance = match date {
+                Some(date) => db::balance_as_of(conn, id, date)?,
+                None => db::current_balance(conn, id)?,
+            };
+            let value = Money::new(balance.value, &currency.code, currency.minor_unit);
+
+            Ok(AccountBalance {
+                id: account.id,
+                acc_type: account.acc_type,
+                currency: account.currency,
+                normal_balance: account.acc_type.normal_money(&value)?,
+                value,
+            })
+        })
+    }
+
+    // Closing balances of an account for every period in the range
+    pub fn balance_history(
+        &self,
+        id: i32,
+        interval: Interval,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<BalanceHistory> {
+        if from > to {
+            return Err(LedgerError::Validation(String::from(
+                "from has to be before to",
+            )));
+        }
+
+        // Stops counting past the limit, a wide daily range would take long to build
+        let points = interval.period_starts(from, to);
+        if points.take(BalanceHistory::MAX_POINTS + 1).count() > BalanceHistory::MAX_POINTS {
+            return Err(LedgerError::Validation(format!(
+                "A balance history can have at most {} points",
+                BalanceHistory::MAX_POINTS
+            )));
+        }
+
+        self.with_connection(|conn| {
+            let account = account::db::get_account(conn, id)?;
+            let currency = currency::db::get_currency(conn, &account.currency)?;
+            let opening = db::balance_as_of(conn, id, from)?;
+            let movements = db::account_movements(conn, id, from, to)?;
+
+            BalanceHistory::new(
+                &account,
+                interval,
+                from,
+                to,
+                &Money::new(opening.value, &currency.code, currency.minor_unit),
+                &movements,
+            )
+        })
+    }
+
+    // Transactions
+
+    pub fn transaction(&self, id: i32) -> Result<TransactionV2> {
+        self.with_connection(|conn| transaction::db::get_transaction(conn, id))
+    }
+
+    pub fn transactions(&self, include_voided: bool) -> Result<Vec<Transaction>> {
+        self.with_connection(|conn| transaction::db::list_transactions(conn, include_voided))
+    }
+
+    pub fn transactions_in_year(
+        &self,
+        year: i32,
+        include_voided: bool,
+    ) -> Result<Vec<Transaction>> {
+        self.with_connection(|conn| {
+            transaction::db::list_transactions_year(conn, year, include_voided)
+        })
+    }
+
+    pub fn transactions_in_month(
+        &self,
+        year: i32,
+        month: u8,
+        include_voided: bool,
+    ) -> Result<Vec<Transaction>> {
+        self.with_connection(|conn| {
+            transaction::db::list_transactions_date(conn, year, month, include_voided)
+        })
+    }
+
+    // Legs in different currencies are balanced through the FX account of
+    // every currency at the exchange rate of the transaction date
+    pub fn create_transaction(&self, new: &NewTransaction) -> Result<TransactionV2> {
+        check_date(new.valid_date())?;
+        let date = new.date();
+
+        self.with_connection(|conn| {
+            let (debits, credits) = balance_legs(conn, &new.debits, &new.credits, date)?;
+
+            transaction::db::create_transaction(conn, &new.name, date, &debits, &credits)
+        })
+    }
+
+    // Replaces the name, entries and optionally the date of a transaction
+    pub fn update_transaction(&self, id: i32, update: &UpdateTransaction) -> Result<TransactionV2> {
+        check_date(update.valid_date())?;
+
+        self.with_connection(|conn| {
+            // The date of the rates used to balance the legs
+            let date = match update.date() {
+                Some(date) => date,
+                None => transaction::db::get_transaction(conn, id)?.date(),
+            };
+            let (debits, credits) = balance_legs(conn, &update.debits, &update.credits, date)?;
+
+            transaction::db::update_transaction(
+                conn,
+                id,
+                &update.name,
+                update.date(),
+                &debits,
+                &credits,
+            )
+        })
+    }
+
+    // Voids a transaction by recording a reversal of it
+    pub fn void_transaction(&self, id: i32) -> Result<TransactionV2> {
+        self.with_connection(|conn| transaction::db::void_transaction(conn, id, Utc::now()))
+    }
+
+    // Voided pairs are kept for the audit trail and can not be deleted
+    pub fn delete_transaction(&self, id: i32) -> Result<()> {
+        self.with_connection(|conn| transaction::db::remove_transaction(conn, id))
+    }
+
+    // Budgets
+
+    pub fn budget(&self, id: i32) -> Result<Budget> {
+        self.with_connection(|conn| budget::db::get_budget(conn, id))
+    }
+
+    pub fn budget_entries(&self, id: i32) -> Result<Vec<BudgetEntry>> {
+        self.with_connection(|conn| budget::db::list_budget_entries(conn, id))
+    }
+
+    // The monthly budget of the current month
+    pub fn current_budget(&self) -> Result<Budget> {
+        let (start, end) = budget::current_period(Utc::now());
+
+        self.with_connection(|conn| budget::db::get_budget_by_date(conn, start, end))
+    }
+
+    // Returns the id of the new budget
+    pub fn create_budget(&self, new: &NewBudget) -> Result<i64> {
+        self.with_connection(|conn| {
+            let budget = check_new_budget(conn, new)?;
+
+            budget::db::create_budget(conn, &budget)
+        })
+    }
+
+    // Creates a budget with an entry for every expense account
+    pub fn generate_budget(&self, new: &NewBudget) -> Result<i64> {
+        self.with_connection(|conn| {
+            let budget = check_new_budget(conn, new)?;
+
+            budget::db::generate_budget(conn, &budget)
+        })
+    }
+
+    pub fn delete_budget(&self, id: i32) -> Result<()> {
+        self.with_connection(|conn| budget::db::remove_budget(conn, id))
+    }
+
+    pub fn add_budget_entry(&self, id: i32, entry: NewBudgetEntry) -> Result<()> {
+        self.with_connection(|conn| {
+            let balance = budget_amount(conn, &entry)?;
+
+            budget::db::add_budget_entry(conn, id, entry.account, balance)
+        })
+    }
+
+    pub fn update_budget_entry(&self, id: i32, entry: NewBudgetEntry) -> Result<()> {
+        self.with_connection(|conn| {
+            let balance = budget_amount(conn, &entry)?;
+
+            budget::db::update_budget_entry(conn, id, entry.account, balance)
+        })
+    }
+
+    pub fn delete_budget_entry(&self, id: i32, entry: NewBudgetEntry) -> Result<()> {
+        self.with_connection(|conn| budget::db::delete_budget_entry(conn, id, entry.account))
+    }
+
+    // Currencies
+
+    pub fn currencies(&self) -> Result<Vec<Currency>> {
+        self.with_connection(|conn| currency::db::list_currencies(conn))
+    }
+
+    pub fn currency(&self, code: &str) -> Result<Currency> {
+        self.with_connection(|conn| currency::db::get_currency(conn, code))
+    }
+
+    pub fn create_currency(&self, new: &Currency) -> Result<Currency> {
+        new.check().map_err(LedgerError::Validation)?;
+
+        self.with_connection(|conn| {
+            currency::db::add_currency(conn, new)?;
+
+            currency::db::get_currency(conn, &new.code)
+        })
+    }
+
+    // Amounts are stored in minor units, so the minor unit of a currency
+    // can only change while no account holds it
+    pub fn update_currency(&self, code: &str, update: &UpdateCurrency) -> Result<Currency> {
+        update.check().map_err(LedgerError::Validation)?;
+
+        self.with_connection(|conn| {
+            let current = currency::db::get_currency(conn, code)?;
+
+            if current.minor_unit != update.minor_unit && currency::db::currency_in_use(conn, code)?
+            {
+                return Err(LedgerError::Conflict(format!(
+                    "Currency {} is in use, its minor_unit can't be changed",
+                    code
+                )));
+            }
+
+            currency::db::update_currency(conn, code, update)?;
+
+            currency::db::get_currency(conn, code)
+        })
+    }
+
+    pub fn delete_currency(&self, code: &str) -> Result<()> {
+        self.with_connection(|conn| {
+            if currency::db::currency_in_use(conn, code)? {
+                return Err(LedgerError::Conflict(format!(
+                    "Currency {} is still in use by an account",
+                    code
+                )));
+            }
+
+            currency::db::remove_currency(conn, code)
+        })
+    }
+
+    // Adds the ISO 4217 currencies the ledger is missing and returns how many
+    pub fn import_currencies(&self) -> Result<u32> {
+        self.with_connection(currency::db::import_iso_4217)
+    }
+
+    // Exchange rates
+
+    pub fn exchange_rates(
+        &self,
+        base: Option<&str>,
+        quote: Option<&str>,
+    ) -> Result<Vec<ExchangeRate>> {
+        self.with_connection(|conn| fx::db::list_rates(conn, base, quote))
+    }
+
+    pub fn exchange_rate(&self, id: i32) -> Result<ExchangeRate> {
+        self.with_connection(|conn| fx::db::get_rate(conn, id))
+    }
+
+    pub fn create_exchange_rate(&self, new: &NewExchangeRate) -> Result<ExchangeRate> {
+        new.check().map_err(LedgerError::Validation)?;
+
+        self.with_connection(|conn| {
+            let id = fx::db::add_rate(conn, new)?;
+
+            fx::db::get_rate(conn, id)
+        })
+    }
+
+    pub fn update_exchange_rate(&self, id: i32, update: &NewExchangeRate) -> Result<ExchangeRate> {
+        update.check().map_err(LedgerError::Validation)?;
+
+        self.with_connection(|conn| {
+            fx::db::update_rate(conn, id, update)?;
+
+            fx::db::get_rate(conn, id)
+        })
+    }
+
+    pub fn delete_exchange_rate(&self, id: i32) -> Result<()> {
+        self.with_connection(|conn| fx::db::remove_rate(conn, id))
+    }
+
+    // Stores every rate or none of them and returns how many were stored
+    pub fn import_exchange_rates(&self, rates: &[NewExchangeRate]) -> Result<u32> {
+        for (i, rate) in rates.iter().enumerate() {
+            rate.check()
+                .map_err(|m| LedgerError::Validation(format!("Rate {}: {}", i, m)))?;
+        }
+
+        self.with_connection(|conn| fx::db::import_rates(conn, rates))
+    }
+
+    pub fn fx_accounts(&self) -> Result<Vec<FxAccount>> {
+        self.with_connection(|conn| fx::db::list_fx_accounts(conn))
+    }
+
+    // The FX account of a currency has to be an open account in that currency
+    pub fn set_fx_account(&self, code: &str, id: i32) -> Result<FxAccount> {
+        self.with_connection(|conn| {
+            currency::db::get_currency(conn, code)?;
+            let acc = open_account(conn, id)?;
+
+            if acc.currency != code {
+                return Err(LedgerError::Validation(format!(
+                    "Account {} holds {}, not {}",
+                    acc.id, acc.currency, code
+                )));
+            }
+
+            fx::db::set_fx_account(conn, code, id)?;
+
+            Ok(FxAccount {
+                currency: String::from(code),
+                account: id,
+            })
+        })
+    }
+
+    pub fn delete_fx_account(&self, code: &str) -> Result<()> {
+        self.with_connection(|conn| fx::db::remove_fx_account(conn, code))
+    }
+
+    // Reports
+
+    pub fn trial_balance(&self, date: DateTime<Utc>) -> Result<TrialBalance> {
+        let totals = self.with_connection(|conn| report::db::account_totals(conn, None, date))?;
+
+        TrialBalance::new(date, totals)
+    }
+
+    pub fn balance_sheet(&self, date: DateTime<Utc>) -> Result<BalanceSheet> {
+        let totals = self.with_connection(|conn| report::db::account_totals(conn, None, date))?;
+
+        BalanceSheet::new(date, &totals)
+    }
+
+    pub fn income_statement(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<IncomeStatement> {
+        if from >= to {
+            return Err(LedgerError::Validation(String::from(
+                "from has to be before to",
+            )));
+        }
+
+        let totals =
+            self.with_connection(|conn| report::db::account_totals(conn, Some(from), to))?;
+
+        IncomeStatement::new(from, to, &totals)
+    }
+
+    // Assets and liabilities converted into one currency at the most recent
+    // rates on or before the date
+    pub fn net_worth(&self, currency: &str, date: DateTime<Utc>) -> Result<NetWorth> {
+        self.with_connection(|conn| {
+            let reporting = currency::db::get_currency(conn, currency)?;
+            let totals = report::db::account_totals(conn, None, date)?;
+
+            let mut rates: HashMap<String, Option<f64>> = HashMap::new();
+            for line in &totals {
+                if line.currency == reporting.code || rates.contains_key(&line.currency) {
+                    continue;
+                }
+
+                let rate = fx::db::rate_as_of(conn, &line.currency, &reporting.code, date)?;
+                rates.insert(line.currency.clone(), rate);
+            }
+
+            NetWorth::new(date, &reporting, &totals, &rates)
+        })
+    }
+
+    // Checks every transaction and entry of the ledger
+    pub fn check_integrity(&self) -> Result<IntegrityReport> {
+        self.with_connection(|conn| db::check_integrity(conn))
+    }
+}
+
+// A supplied date has to be valid
+fn check_date(valid_date: bool) -> Result<()> {
+    if !valid_date {
+        return Err(LedgerError::Validation(String::from(
+            "date has to be an RFC 3339 timestamp",
+        )));
+    }
+
+    Ok(())
+}
+
+// A leg has to point to an existing account that is not archived
+fn open_account(conn: &Connection, id: i32) -> Result<Account> {
+    let acc = match account::db::get_account(conn, id) {
+        Ok(v) => v,
+        Err(LedgerError::NotFound(m)) => return Err(LedgerError::Validation(m)),
+        Err(e) => return Err(e),
+    };
+
+    if acc.archived {
+        return Err(LedgerError::Validation(format!(
+            "Account {} is archived",
+            acc.id
+        )));
+    }
+
+    Ok(acc)
+}
+
+// An amount of an account in minor units of the account's currency
+fn minor_units(amount: &Amount, account: i32, currency: &Currency) -> Result<i64> {
+    amount
+        .minor_units(currency.minor_unit)
+        .map_err(|m| LedgerError::Validation(format!("Account {}: {}", account, m)))
+}
+
+// Legs in a single currency have to add up to the same amount. Legs across
+// currencies have to add up at the exchange rates of the date, converted into
+// the currency of the first debit, and every currency is then balanced by a
+// leg in its FX account. Returns the legs to store
+fn balance_legs(
+    conn: &Connection,
+    debits: &[NewEntry],
+    credits: &[NewEntry],
+    date: DateTime<Utc>,
+) -> Result<(Vec<Posting>, Vec<Posting>)> {
+    // Debits - Credits of every currency, in the order the currencies appear
+    let mut nets: Vec<(Currency, i64)> = Vec::new();
+
+    let mut post = |legs: &[NewEntry], sign: i64| -> Result<Vec<Posting>> {
+        let mut postings = Vec::new();
+
+        for leg in legs {
+            let acc = open_account(conn, leg.account)?;
+            let currency = currency::db::get_currency(conn, &acc.currency)?;
+            let balance = minor_units(&leg.balance, acc.id, &currency)?;
+
+            let amount = if sign < 0 {
+                money::neg(balance)?
+            } else {
+                balance
+            };
+
+            match nets.iter_mut().find(|(c, _)| c.code == currency.code) {
+                Some((_, net)) => *net = money::add(*net, amount)?,
+                None => nets.push((currency, amount)),
+            }
+
+            postings.push(Posting {
+                account: acc.id,
+                balance,
+            });
+        }
+
+        Ok(postings)
+    };
+
+    let mut debits = post(debits, 1)?;
+    let mut credits = post(credits, -1)?;
+
+    if !legs_positive(&debits, &credits) {
+        return Err(LedgerError::Validation(String::from(
+            "A transaction needs positive debits and credits",
+        )));
+    }
+
+    if nets.len() == 1 {
+        if !legs_balanced(&debits, &credits) {
+            return Err(LedgerError::Validation(String::from(
+                "The debits and credits of a transaction have to add up to the same amount",
+            )));
+        }
+
+        return Ok((debits, credits));
+    }
+
+    let reference = &nets[0].0;
+
+    let mut value = 0;
+    for (currency, net) in &nets {
+        if currency.code == reference.code {
+            value = money::add(value, *net)?;
+            continue;
+        }
+
+        let rate =
+            fx::db::rate_as_of(conn, &currency.code, &reference.code, date)?.ok_or_else(|| {
+                LedgerError::Validation(format!(
+                    "No exchange rate between {} and {} on or before {}",
+                    currency.code, reference.code, date
+                ))
+            })?;
+
+        let converted = fx::data::convert(*net, rate, currency.minor_unit, reference.minor_unit)
+            .ok_or_else(|| LedgerError::overflow(format!("{} {}", net, currency.code)))?;
+        value = money::add(value, converted)?;
+    }
+
+    // Every conversion can be up to a minor unit off
+    if value.abs() >= nets.len() as i64 {
+        return Err(LedgerError::Validation(format!(
+            "The debits and credits do not add up to the same amount in {} at the exchange rates of {}",
+            reference.code, date
+        )));
+    }
+
+    for (currency, net) in nets {
+        if net == 0 {
+            continue;
+        }
+
+        let account = fx::db::get_fx_account(conn, &currency.code)?.ok_or_else(|| {
+            LedgerError::Validation(format!(
+                "Transactions across currencies need an FX account for {}",
+                currency.code
+            ))
+        })?;
+        let leg = Posting {
+            account,
+            balance: net
+                .checked_abs()
+                .ok_or_else(|| LedgerError::overflow(format!("{} {}", net, currency.code)))?,
+        };
+
+        if net > 0 {
+            credits.push(leg);
+        } else {
+            debits.push(leg);
+        }
+    }
+
+    Ok((debits, credits))
+}
+
+// Budgeted amounts are in the currency of their account
+fn budget_amount(conn: &Connection, entry: &NewBudgetEntry) -> Result<i64> {
+    let acc = match account::db::get_account(conn, entry.account) {
+        Ok(v) => v,
+        Err(LedgerError::NotFound(m)) => return Err(LedgerError::Validation(m)),
+        Err(e) => return Err(e),
+    };
+    let currency = currency::db::get_currency(conn, &acc.currency)?;
+
+    minor_units(&entry.balance, acc.id, &currency)
+}
+
+// Parses the dates of a new budget and checks that it does not overlap
+// with an existing one
+fn check_new_budget(conn: &Connection, new: &NewBudget) -> Result<Budget> {
+    let open_time = DateTime::parse_from_rfc3339(&new.open);
+    let close_time = DateTime::parse_from_rfc3339(&new.close);
+
+    if open_time.is_err() || close_time.is_err() {
+        return Err(LedgerError::Validation(String::from(
+            "open and close have to be RFC 3339 timestamps",
+        )));
+    }
+
+    let open_utc = open_time.unwrap().with_timezone(&Utc);
+    let close_utc = close_time.unwrap().with_timezone(&Utc);
+
+    if budget::db::check_if_budget_exists(conn, open_utc, close_utc + Duration::days(1))? {
+        error!(
+            "Budget already exists for {open} - {close}",
+            open = open_utc,
+            close = close_utc
+        );
+        return Err(LedgerError::Conflict(format!(
+            "A budget already exists for {} - {}",
+            open_utc, close_utc
+        )));
+    }
+
+    if close_utc < open_utc {
+        error!(
+            "Wrong Request. {close} is smaller than {open}",
+            close = close_utc,
+            open = open_utc
+        );
+        return Err(LedgerError::Validation(String::from(
+            "close has to be after open",
+        )));
+    }
+
+    Ok(Budget::new(-1, &new.name, open_utc, close_utc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::data::AccountTypeValue;
+    use chrono::TimeZone;
+
+    fn new_account(ledger: &Ledger, acc_type: &str, name: &str, currency: &str) -> Account {
+        ledger
+            .create_account(&NewAccount {
+                acc_type: AccountTypeValue::Name(String::from(acc_type)),
+                name: String::from(name),
+                currency: String::from(currency),
+                parent: None,
+                description: None,
+            })
+            .unwrap()
+    }
+
+    fn new_transaction(debit: i32, credit: i32, balance: i64) -> NewTransaction {
+        NewTransaction {
+            name: String::from("Weekly shop"),
+            date: Some(String::from("2020-01-15T18:00:00+00:00")),
+            debits: vec![NewEntry {
+                account: debit,
+                balance: Amount::from(balance),
+            }],
+            credits: vec![NewEntry {
+                account: credit,
+                balance: Amount::from(balance),
+            }],
+        }
+    }
+
+    #[test]
+    fn posts_transactions_in_process() {
+        let ledger = Ledger::open_in_memory().unwrap();
+        assert_eq!(
+            ledger.schema_version().unwrap(),
+            migrations::latest_version()
+        );
+
+        let current = new_account(&ledger, "Assets", "Current", "GBP");
+        let food = new_account(&ledger, "Expenses", "Food", "GBP");
+
+        let created = ledger
+            .create_transaction(&new_transaction(food.id, current.id, 4500))
+            .unwrap();
+
+        assert_eq!(ledger.transaction(created.id()).unwrap().entries().len(), 2);
+        assert_eq!(
+            ledger.balance(food.id, None).unwrap().normal_balance.amount,
+            4500
+        );
+        assert_eq!(
+            ledger.balance(current.id, None).unwrap().normal_balance,
+            Money::new(-4500, "GBP", 2)
+        );
+        assert!(ledger.check_integrity().unwrap().integrity);
+    }
+
+    #[test]
+    fn amounts_beyond_32_bits_add_up_until_a_total_would_overflow() {
+        let ledger = Ledger::open_in_memory().unwrap();
+
+        let brokerage = new_account(&ledger, "Assets", "Brokerage", "GBP");
+        let equity = new_account(&ledger, "Equities", "Opening Balance", "GBP");
+
+        // 50 million pounds in pence is well past i32::MAX
+        ledger
+            .create_transaction(&new_transaction(brokerage.id, equity.id, 5_000_000_000))
+            .unwrap();
+
+        assert_eq!(
+            ledger
+                .balance(brokerage.id, None)
+                .unwrap()
+                .value
+                .formatted(),
+            "50000000.00"
+        );
+        assert_eq!(
+            ledger.trial_balance(Utc::now()).unwrap().totals[0]
+                .debits
+                .amount,
+            5_000_000_000
+        );
+
+        let mut split = new_transaction(brokerage.id, equity.id, i64::MAX);
+        split.debits.push(split.debits[0].clone());
+        match ledger.create_transaction(&split) {
+            Err(LedgerError::Overflow(_)) => {}
+            other => panic!("Expected an overflow, got {:?}", other),
+        }
+
+        // The posting fits, but the totals of both accounts would not
+        match ledger.create_transaction(&new_transaction(brokerage.id, equity.id, i64::MAX)) {
+            Err(LedgerError::Overflow(_)) => {}
+            other => panic!("Expected an overflow, got {:?}", other),
+        }
+
+        assert_eq!(ledger.transactions(true).unwrap().len(), 1);
+        assert_eq!(
+            ledger.balance(brokerage.id, None).unwrap().value.amount,
+            5_000_000_000
+        );
+        assert!(ledger.account_tree().is_ok());
+        assert!(ledger.trial_balance(Utc::now()).is_ok());
+    }
+
+    #[test]
+    fn register_and_history_ranges_are_validated() {
+        let ledger = Ledger::open_in_memory().unwrap();
+        let current = new_account(&ledger, "Assets", "Current", "GBP");
+
+        let from = Utc.ymd(1970, 1, 1).and_hms(0, 0, 0);
+        let to = Utc.ymd(9999, 12, 31).and_hms(0, 0, 0);
+
+        for result in &[
+            ledger
+                .register(current.id, Some(to), Some(from), 10, 0)
+                .map(|_| ()),
+            ledger.register(current.id, None, None, 0, 0).map(|_| ()),
+            ledger
+                .register(current.id, None, None, RegisterQuery::MAX_LIMIT + 1, 0)
+                .map(|_| ()),
+            ledger
+                .balance_history(current.id, Interval::Monthly, to, from)
+                .map(|_| ()),
+            ledger
+                .balance_history(current.id, Interval::Daily, from, to)
+                .map(|_| ()),
+        ] {
+            match result {
+                Err(LedgerError::Validation(_)) => {}
+                other => panic!("Expected a validation error, got {:?}", other),
+            }
+        }
+
+        assert!(ledger
+            .balance_history(current.id, Interval::Monthly, from, from)
+            .is_ok());
+    }
+
+    #[test]
+    fn net_worth_uses_the_rates_as_of_its_date() {
+        let ledger = Ledger::open_in_memory().unwrap();
+
+        let current = new_account(&ledger, "Assets", "Current", "GBP");
+        let euros = new_account(&ledger, "Assets", "Euro Account", "EUR");
+        let dollars = new_account(&ledger, "Assets", "Dollar Account", "USD");
+        let opening = new_account(&ledger, "Equities", "Opening Balance", "GBP");
+        let opening_eur = new_account(&ledger, "Equities", "Opening Balance EUR", "EUR");
+        let opening_usd = new_account(&ledger, "Equities", "Opening Balance USD", "USD");
+
+        for (debit, credit) in &[
+            (current.id, opening.id),
+            (euros.id, opening_eur.id),
+            (dollars.id, opening_usd.id),
+        ] {
+            ledger
+                .create_transaction(&new_transaction(*debit, *credit, 10000))
+                .unwrap();
+        }
+
+        let rate = |date: &str, rate: f64| NewExchangeRate {
+            base: String::from("EUR"),
+            quote: String::from("GBP"),
+            date: String::from(date),
+            rate,
+            source: None,
+        };
+        ledger
+            .import_exchange_rates(&[
+                rate("2020-01-01T00:00:00+00:00", 0.8),
+                rate("2020-02-01T00:00:00+00:00", 0.9),
+            ])
+            .unwrap();
+
+        let january = Utc.ymd(2020, 1, 31).and_hms(23, 59, 59);
+        let worth = ledger.net_worth("GBP", january).unwrap();
+
+        assert_eq!(worth.total.amount, 18000);
+        assert!(!worth.complete);
+        let flagged: Vec<i32> = worth
+            .accounts
+            .iter()
+            .filter(|a| a.missing_rate)
+            .map(|a| a.id)
+            .collect();
+        assert_eq!(flagged, vec![dollars.id]);
+
+        let later = ledger.net_worth("GBP", Utc::now()).unwrap();
+        assert_eq!(later.total.amount, 19000);
+
+        assert_eq!(
+            ledger.net_worth("XXQ", Utc::now()).unwrap_err().code(),
+            "not_found"
+        );
+    }
+
+    #[test]
+    fn decimal_amounts_follow_the_minor_unit_of_the_account() {
+        let ledger = Ledger::open_in_memory().unwrap();
+
+        let current = new_account(&ledger, "Assets", "Current", "GBP");
+        let food = new_account(&ledger, "Expenses", "Food", "GBP");
+        let yen = new_account(&ledger, "Assets", "Wallet", "JPY");
+        let travel = new_account(&ledger, "Expenses", "Travel", "JPY");
+
+        let mut shop = new_transaction(food.id, current.id, 0);
+        shop.debits[0].balance = Amount::Decimal(String::from("45.5"));
+        shop.credits[0].balance = Amount::from(4550);
+        ledger.create_transaction(&shop).unwrap();
+
+        assert_eq!(
+            ledger.balance(food.id, None).unwrap().value.formatted(),
+            "45.50"
+        );
+
+        let mut fare = new_transaction(travel.id, yen.id, 0);
+        fare.debits[0].balance = Amount::Decimal(String::from("1200.50"));
+        fare.credits[0].balance = Amount::Decimal(String::from("1200.50"));
+
+        match ledger.create_transaction(&fare) {
+            Err(LedgerError::Validation(m)) => assert!(m.contains("decimal places")),
+            other => panic!("Expected a validation error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn transactions_go_through_the_same_checks_as_the_api() {
+        let ledger = Ledger::open_in_memory().unwrap();
+        let current = new_account(&ledger, "Assets", "Current", "GBP");
+        let food = new_account(&ledger, "Expenses", "Food", "GBP");
+        let euros = new_account(&ledger, "Assets", "Euro Current", "EUR");
+
+        let mut unbalanced = new_transaction(food.id, current.id, 4500);
+        unbalanced.credits[0].balance = Amount::from(4000);
+
+        match ledger.create_transaction(&unbalanced) {
+            Err(LedgerError::Validation(_)) => {}
+            other => panic!("Expected a validation error, got {:?}", other),
+        }
+
+        match ledger.create_transaction(&new_transaction(food.id, euros.id, 4500)) {
+            Err(LedgerError::Validation(_)) => {}
+            other => panic!("Expected a validation error, got {:?}", other),
+        }
+
+        assert!(ledger.transactions(true).unwrap().is_empty());
+    }
+
+    fn gbp_eur_rate(rate: f64) -> NewExchangeRate {
+        NewExchangeRate {
+            base: String::from("GBP"),
+            quote: String::from("EUR"),
+            date: String::from("2020-01-14T00:00:00+00:00"),
+            rate,
+            source: None,
+        }
+    }
+
+    #[test]
+    fn transfers_across_currencies_go_through_fx_accounts() {
+        let ledger = Ledger::open_in_memory().unwrap();
+        let current = new_account(&ledger, "Assets", "Current", "GBP");
+        let brokerage = new_account(&ledger, "Assets", "Brokerage", "EUR");
+        let fx_sterling = new_account(&ledger, "Equities", "FX Sterling", "GBP");
+        let fx_euro = new_account(&ledger, "Equities", "FX Euro", "EUR");
+
+        // 100.00 GBP in, 115.00 EUR out
+        let mut transfer = new_transaction(brokerage.id, current.id, 11500);
+        transfer.credits[0].balance = Amount::from(10000);
+
+        match ledger.create_transaction(&transfer) {
+            Err(LedgerError::Validation(m)) => assert!(m.starts_with("No exchange rate")),
+            other => panic!("Expected a validation error, got {:?}", other),
+        }
+
+        ledger.create_exchange_rate(&gbp_eur_rate(1.15)).unwrap();
+
+        match ledger.create_transaction(&transfer) {
+            Err(LedgerError::Validation(m)) => assert!(m.ends_with("FX account for EUR")),
+            other => panic!("Expected a validation error, got {:?}", other),
+        }
+
+        ledger.set_fx_account("GBP", fx_sterling.id).unwrap();
+        ledger.set_fx_account("EUR", fx_euro.id).unwrap();
+
+        let created = ledger.create_transaction(&transfer).unwrap();
+
+        assert_eq!(created.entries().len(), 4);
+        assert_eq!(
+            ledger.balance(brokerage.id, None).unwrap().value.amount,
+            11500
+        );
+        assert_eq!(
+            ledger.balance(current.id, None).unwrap().value.amount,
+            -10000
+        );
+        assert_eq!(
+            ledger.balance(fx_sterling.id, None).unwrap().value.amount,
+            10000
+        );
+        assert_eq!(
+            ledger.balance(fx_euro.id, None).unwrap().value.amount,
+            -11500
+        );
+        assert!(ledger.check_integrity().unwrap().integrity);
+    }
+
+    #[test]
+    fn transfers_across_currencies_have_to_match_the_rate() {
+        let ledger = Ledger::open_in_memory().unwrap();
+        let current = new_account(&ledger, "Assets", "Current", "GBP");
+        let brokerage = new_account(&ledger, "Assets", "Brokerage", "EUR");
+        let fx_sterling = new_account(&ledger, "Equities", "FX Sterling", "GBP");
+        let fx_euro = new_account(&ledger, "Equities", "FX Euro", "EUR");
+
+        ledger.create_exchange_rate(&gbp_eur_rate(1.15)).unwrap();
+        ledger.set_fx_account("GBP", fx_sterling.id).unwrap();
+        ledger.set_fx_account("EUR", fx_euro.id).unwrap();
+
+        let mut transfer = new_transaction(brokerage.id, current.id, 12000);
+        transfer.credits[0].balance = Amount::from(10000);
+
+        match ledger.create_transaction(&transfer) {
+            Err(LedgerError::Validation(_)) => {}
+            other => panic!("Expected a validation error, got {:?}", other),
+        }
+
+        match ledger.set_fx_account("EUR", current.id) {
+            Err(LedgerError::Validation(_)) => {}
+            other => panic!("Expected a validation error, got {:?}", other),
+        }
+
+        assert!(ledger.transactions(true).unwrap().is_empty());
+    }
+
+    #[test]
+    fn voided_transactions_and_used_accounts_are_kept() {
+        let ledger = Ledger::open_in_memory().unwrap();
+        let current = new_account(&ledger, "Assets", "Current", "GBP");
+        let food = new_account(&ledger, "Expenses", "Food", "GBP");
+
+        let created = ledger
+            .create_transaction(&new_transaction(food.id, current.id, 4500))
+            .unwrap();
+        ledger.void_transaction(created.id()).unwrap();
+
+        match ledger.delete_transaction(created.id()) {
+            Err(LedgerError::Conflict(_)) => {}
+            other => panic!("Expected a conflict, got {:?}", other),
+        }
+
+        match ledger.delete_account(food.id) {
+            Err(LedgerError::Conflict(_)) => {}
+            other => panic!("Expected a conflict, got {:?}", other),
+        }
+
+        assert_eq!(ledger.balance(food.id, None).unwrap().value.amount, 0);
+    }
+
+    #[test]
+    fn currencies_in_use_are_kept() {
+        let ledger = Ledger::open_in_memory().unwrap();
+        new_account(&ledger, "Assets", "Current", "GBP");
+
+        match ledger.delete_currency("GBP") {
+            Err(LedgerError::Conflict(_)) => {}
+            other => panic!("Expected a conflict, got {:?}", other),
+        }
+
+        let update = UpdateCurrency {
+            numeric_code: 826,
+            minor_unit: 3,
+            name: String::from("Pound Sterling"),
+        };
+        match ledger.update_currency("GBP", &update) {
+            Err(LedgerError::Conflict(_)) => {}
+            other => panic!("Expected a conflict, got {:?}", other),
+        }
+
+        ledger.delete_currency("JPY").unwrap();
+        assert_eq!(ledger.import_currencies().unwrap(), 1);
+    }
+}
@@ -0,0 +1,26 @@
+//! Double entry bookkeeping on SQLite. `Ledger` is the entry point, shared
+//! by the HTTP server and the `pig` command line tool.
+
+extern crate chrono;
+extern crate rusqlite;
+
+pub mod account;
+pub mod api;
+pub mod budget;
+pub mod config;
+pub mod currency;
+pub mod datastruct;
+pub mod db;
+pub mod error;
+pub mod fx;
+mod ledger;
+pub mod migrations;
+pub mod money;
+pub mod report;
+pub mod routes;
+pub mod transaction;
+
+pub use crate::ledger::Ledger;
+
+#[macro_use]
+extern crate log;
@@ -1,124 +1,96 @@
-// Accounts
-// Assets = 0
-// Liabilities = 1
-// Equities = 2
-// Revenue = 3
-// Expenses = 4
-// Gains = 5
-// Losses = 6
-
-// Assets = Liabilities + Equity
-
-// Assets DB + CR -
-// Liabilities DB - CR +
-
-extern crate chrono;
-extern crate rusqlite;
-
-mod account;
-mod api;
-mod budget;
-mod datastruct;
-mod db;
-mod transaction;
-
 #[macro_use]
 extern crate log;
 
+use std::fmt;
 use std::io;
 
 use actix_cors::Cors;
-use actix_web::middleware::Logger;
+use actix_web::middleware::{Condition, Logger};
 use actix_web::{web, App, HttpServer};
 
 use r2d2_sqlite::SqliteConnectionManager;
 
-use env_logger;
+use ledger::config::{self, Config};
+use ledger::error::LedgerError;
+use ledger::{db, routes, Ledger};
+use structopt::StructOpt;
+
+// Without an explicit origin actix-cors allows any
+fn cors(origins: &[String]) -> actix_cors::CorsFactory {
+    let cors = if origins.iter().any(|o| o == "*") {
+        Cors::new()
+    } else {
+        origins
+            .iter()
+            .fold(Cors::new(), |cors, origin| cors.allowed_origin(origin))
+    };
+
+    cors.max_age(3600).finish()
+}
+
+// Startup errors end the process with a message instead of a panic
+fn or_exit<T, E: fmt::Display>(result: Result<T, E>, context: &str) -> T {
+    match result {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("{}: {}", context, e);
+            std::process::exit(1);
+        }
+    }
+}
 
 #[actix_rt::main]
 async fn main() -> io::Result<()> {
-    // std::env::set_var("RUST_LOG", "actix_web=info");
-    std::env::set_var("RUST_LOG", "info");
-    env_logger::init();
+    let config = or_exit(
+        Config::load(config::Args::from_args()),
+        "Invalid configuration",
+    );
+    config.init_logger();
+
+    let manager = SqliteConnectionManager::file(&config.database).with_init(db::init_connection);
+    let pool = or_exit(
+        r2d2::Pool::builder()
+            .max_size(config.pool_size)
+            .build(manager),
+        "Failed to open the database",
+    );
 
-    let manager = SqliteConnectionManager::file("ledger.db");
-    let pool = r2d2::Pool::new(manager).unwrap();
+    // A new database gets the full schema, an existing one any pending migrations
+    let ledger = or_exit(Ledger::from_pool(pool), "Failed to open the ledger");
+    info!(
+        "Database {} at schema version {}",
+        config.database.display(),
+        or_exit(ledger.schema_version(), "Failed to read the schema version")
+    );
+    let ledger = web::Data::new(ledger);
+
+    let cors_origins = config.cors_origins.clone();
 
     let app = move || {
         debug!("Constructing the App");
 
         App::new()
             .wrap(Logger::default())
-            .wrap(Cors::new().max_age(3600).finish())
-            .data(pool.clone())
-            .service(web::resource("/").route(web::get().to(account::list_accounts)))
-            .service(web::resource("/currencies").route(web::get().to(api::list_currencies)))
-            .service(web::resource("/integrity").route(web::get().to(api::check_ledger_integrity)))
-            .service(
-                web::scope("/transactions")
-                    .service(
-                        web::resource("")
-                            .route(web::get().to(transaction::list_transactions))
-                            .route(web::post().to(transaction::create_transaction)),
-                    )
-                    .service(
-                        web::resource("/{id}")
-                            .route(web::get().to(transaction::get_transaction))
-                            .route(web::put().to(transaction::update_transaction))
-                            .route(web::delete().to(transaction::delete_transaction)),
-                    ),
-            )
-            .service(
-                web::scope("/account")
-                    .service(web::resource("/").route(web::post().to(account::create_account)))
-                    .service(
-                        web::resource("/{id}")
-                            .route(web::get().to(account::get_account))
-                            .route(web::delete().to(account::delete_account)),
-                    )
-                    .service(
-                        web::resource("/{id}/balance")
-                            .route(web::get().to(api::get_account_balance)),
-                    ),
-            )
-            .service(
-                web::scope("/accounts")
-                    .service(web::resource("").route(web::get().to(account::list_accounts)))
-                    .service(
-                        web::resource("/asset").route(web::get().to(account::list_asset_accounts)),
-                    )
-                    .service(
-                        web::resource("/expense")
-                            .route(web::get().to(account::list_expense_accounts)),
-                    ),
+            .wrap(Condition::new(
+                !cors_origins.is_empty(),
+                cors(&cors_origins),
+            ))
+            .app_data(ledger.clone())
+            // Malformed bodies and query strings get the same problem body as every other error
+            .app_data(
+                web::JsonConfig::default()
+                    .error_handler(|err, _req| LedgerError::Validation(err.to_string()).into()),
             )
-            .service(
-                web::scope("/budget")
-                    .service(
-                        web::resource("")
-                            .route(web::get().to(budget::get_current_budget))
-                            .route(web::post().to(budget::create_budget)),
-                    )
-                    .service(
-                        web::resource("/generate").route(web::post().to(budget::generate_budget)),
-                    )
-                    .service(
-                        web::scope("/{id}")
-                            .service(
-                                web::resource("")
-                                    .route(web::get().to(budget::get_budget))
-                                    .route(web::delete().to(budget::delete_budget)),
-                            )
-                            .service(
-                                web::resource("/entry")
-                                    .route(web::post().to(budget::add_entry_to_budget))
-                                    .route(web::put().to(budget::update_entry_in_budget))
-                                    .route(web::delete().to(budget::delete_entry_in_budget)),
-                            ),
-                    ),
+            .app_data(
+                web::QueryConfig::default()
+                    .error_handler(|err, _req| LedgerError::Validation(err.to_string()).into()),
             )
+            .configure(routes::configure)
     };
 
     debug!("Starting server");
-    HttpServer::new(app).bind("localhost:8088")?.run().await
+    HttpServer::new(app)
+        .bind(config.bind_address())?
+        .run()
+        .await
 }
@@ -0,0 +1,161 @@
+use chrono::Utc;
+use rusqlite::{params, Connection, NO_PARAMS};
+
+// Every migration in the order it is applied. The version of a migration is its
+// position in the list, so released migrations are never changed or reordered
+const MIGRATIONS: &[(&str, &str)] = &[
+    (
+        "initial",
+        include_str!("../data/migrations/0001_initial.sql"),
+    ),
+    (
+        "hierarchy_and_voiding",
+        include_str!("../data/migrations/0002_hierarchy_and_voiding.sql"),
+    ),
+    (
+        "ledger_constraints",
+        include_str!("../data/migrations/0003_ledger_constraints.sql"),
+    ),
+    (
+        "iso_4217",
+        include_str!("../data/migrations/0004_iso_4217.sql"),
+    ),
+    (
+        "exchange_rates",
+        include_str!("../data/migrations/0005_exchange_rates.sql"),
+    ),
+    (
+        "single_reversal",
+        include_str!("../data/migrations/0006_single_reversal.sql"),
+    ),
+];
+
+// The schema version the code expects
+pub fn latest_version() -> u32 {
+    MIGRATIONS.len() as u32
+}
+
+// Version of the schema applied to the database. 0 for a new database
+pub fn current_version(conn: &Connection) -> rusqlite::Result<u32> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS \"SchemaMigrations\" (
+            \"version\"	INTEGER NOT NULL PRIMARY KEY,
+            \"name\"	TEXT NOT NULL,
+            \"applied\"	TEXT NOT NULL
+        );",
+    )?;
+
+    conn.query_row(
+        "SELECT ifnull(MAX(version), 0) FROM SchemaMigrations",
+        NO_PARAMS,
+        |row| row.get(0),
+    )
+}
+
+// Creates or upgrades the schema of the database and returns its version.
+// Every migration is applied in its own database transaction
+pub fn migrate(conn: &Connection) -> rusqlite::Result<u32> {
+    let current = current_version(conn)?;
+
+    for (i, (name, sql)) in MIGRATIONS.iter().enumerate().skip(current as usize) {
+        let version = i as u32 + 1;
+
+        conn.execute_batch("BEGIN")?;
+
+        let result = conn.execute_batch(sql).and_then(|_| {
+            conn.execute(
+                "INSERT INTO SchemaMigrations (version, name, applied) VALUES (?1, ?2, ?3)",
+                params![version, *name, Utc::now()],
+            )
+        });
+
+        match result {
+            Ok(_) => conn.execute_batch("COMMIT")?,
+            Err(e) => {
+                conn.execute_batch("ROLLBACK")?;
+                error!("Migration {} {} failed with {}", version, name, e);
+                return Err(e);
+            }
+        }
+
+        info!("Applied migration {} {}", version, name);
+    }
+
+    Ok(latest_version())
+}
+
+// An in memory ledger with the full schema for tests. Every connection of the
+// pool is its own database, so tests use one connection at a time
+#[cfg(test)]
+pub fn memory_pool() -> r2d2::Pool<r2d2_sqlite::SqliteConnectionManager> {
+    let manager = r2d2_sqlite::SqliteConnectionManager::memory().with_init(|c| {
+        crate::db::init_connection(c)?;
+        migrate(c).map(|_| ())
+    });
+
+    r2d2::Pool::new(manager).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_database_is_migrated_to_the_latest_version() {
+        let conn = Connection::open_in_memory().unwrap();
+
+        assert_eq!(current_version(&conn).unwrap(), 0);
+        assert_eq!(migrate(&conn).unwrap(), latest_version());
+        assert_eq!(current_version(&conn).unwrap(), latest_version());
+
+        let gbp: String = conn
+            .query_row(
+                "SELECT name FROM Currency WHERE code = 'GBP'",
+                NO_PARAMS,
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(gbp, "Pound Sterling");
+    }
+
+    #[test]
+    fn migrating_twice_changes_nothing() {
+        let conn = Connection::open_in_memory().unwrap();
+        migrate(&conn).unwrap();
+
+        conn.execute(
+            "INSERT INTO Accounts (type, name, currency) VALUES (0, 'Current', 'GBP')",
+            NO_PARAMS,
+        )
+        .unwrap();
+
+        assert_eq!(migrate(&conn).unwrap(), latest_version());
+
+        let accounts: i32 = conn
+            .query_row("SELECT COUNT(*) FROM Accounts", NO_PARAMS, |row| row.get(0))
+            .unwrap();
+        assert_eq!(accounts, 1);
+    }
+
+    #[test]
+    fn database_from_the_original_schema_is_upgraded() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(MIGRATIONS[0].1).unwrap();
+        conn.execute_batch(
+            "INSERT INTO Currency (code, numeric_code, minor_unit, name) VALUES ('GBP', 826, 2, 'Pound Sterling');
+            INSERT INTO Accounts (type, name, currency) VALUES (0, 'Current', 'GBP');",
+        )
+        .unwrap();
+
+        migrate(&conn).unwrap();
+
+        let archived: bool = conn
+            .query_row(
+                "SELECT archived FROM Accounts WHERE id = 1",
+                NO_PARAMS,
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert!(!archived);
+    }
+}
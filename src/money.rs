@@ -0,0 +1,273 @@
+use crate::error::{self, LedgerError};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use serde_derive::Deserialize;
+use std::fmt;
+use std::str::FromStr;
+
+// An amount in minor units of its currency, e.g. 1234 GBP is 12.34
+#[derive(Debug, Clone, PartialEq)]
+pub struct Money {
+    pub amount: i64,
+    pub currency: String,
+    // Number of decimal places of the currency
+    pub minor_unit: i32,
+}
+
+impl Money {
+    pub fn new(amount: i64, currency: &str, minor_unit: i32) -> Money {
+        Money {
+            amount,
+            currency: String::from(currency),
+            minor_unit,
+        }
+    }
+
+    // The same currency with a different amount
+    pub fn with_amount(&self, amount: i64) -> Money {
+        Money::new(amount, &self.currency, self.minor_unit)
+    }
+
+    // The amount as a decimal in the currency, e.g. "12.34" or "-0.05"
+    pub fn formatted(&self) -> String {
+        format_minor_units(self.amount, self.minor_unit)
+    }
+
+    pub fn checked_add(&self, amount: i64) -> error::Result<Money> {
+        Ok(self.with_amount(add(self.amount, amount)?))
+    }
+
+    pub fn checked_sub(&self, amount: i64) -> error::Result<Money> {
+        Ok(self.with_amount(sub(self.amount, amount)?))
+    }
+}
+
+// Amounts are added up with checked arithmetic, so a total that does not fit
+// into 64 bits is an error instead of a panic or a wrapped around value
+pub fn add(a: i64, b: i64) -> error::Result<i64> {
+    a.checked_add(b)
+        .ok_or_else(|| LedgerError::overflow(format!("{} + {}", a, b)))
+}
+
+pub fn sub(a: i64, b: i64) -> error::Result<i64> {
+    a.checked_sub(b)
+        .ok_or_else(|| LedgerError::overflow(format!("{} - {}", a, b)))
+}
+
+pub fn neg(a: i64) -> error::Result<i64> {
+    a.checked_neg()
+        .ok_or_else(|| LedgerError::overflow(format!("-({})", a)))
+}
+
+pub fn total(amounts: impl IntoIterator<Item = i64>) -> error::Result<i64> {
+    amounts.into_iter().try_fold(0, add)
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.formatted(), self.currency)
+    }
+}
+
+// Responses carry both the raw minor units and the formatted decimal
+impl Serialize for Money {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Money", 3)?;
+        state.serialize_field("amount", &self.amount)?;
+        state.serialize_field("currency", &self.currency)?;
+        state.serialize_field("formatted", &self.formatted())?;
+        state.end()
+    }
+}
+
+pub fn format_minor_units(amount: i64, minor_unit: i32) -> String {
+    if minor_unit <= 0 {
+        return amount.to_string();
+    }
+
+    let scale = 10u64.pow(minor_unit as u32);
+    let sign = if amount < 0 { "-" } else { "" };
+    let abs = amount.unsigned_abs();
+
+    format!(
+        "{}{}.{:0width$}",
+        sign,
+        abs / scale,
+        abs % scale,
+        width = minor_unit as usize
+    )
+}
+
+// Parses a decimal like "12.34" or "-0.5" into minor units. More decimal
+// places than the currency has are rejected instead of rounded
+pub fn parse_decimal(value: &str, minor_unit: i32) -> Result<i64, String> {
+    let invalid = || format!("{} is not a decimal amount", value);
+    let too_large = || format!("{} is too large", value);
+
+    let trimmed = value.trim();
+    let (negative, digits) = match trimmed.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, trimmed),
+    };
+
+    let mut parts = digits.splitn(2, '.');
+    let whole = parts.next().unwrap_or_default();
+    let fraction = parts.next();
+
+    let all_digits = |v: &str| !v.is_empty() && v.chars().all(|c| c.is_ascii_digit());
+    if !all_digits(whole) || !fraction.map(all_digits).unwrap_or(true) {
+        return Err(invalid());
+    }
+
+    let fraction = fraction.unwrap_or_default();
+    let places = minor_unit.max(0) as usize;
+    if fraction.len() > places {
+        return Err(format!("{} has more than {} decimal places", value, places));
+    }
+
+    let scale = 10i64.pow(places as u32);
+    let whole: i64 = whole.parse().map_err(|_| too_large())?;
+    let fraction: i64 = if fraction.is_empty() {
+        0
+    } else {
+        format!("{:0<width$}", fraction, width = places)
+            .parse()
+            .map_err(|_| invalid())?
+    };
+
+    let amount = whole
+        .checked_mul(scale)
+        .and_then(|v| v.checked_add(fraction))
+        .ok_or_else(too_large)?;
+
+    Ok(if negative { -amount } else { amount })
+}
+
+// An amount in a request. A number is taken as minor units and a string as
+// a decimal in the currency of the account, so 1234 and "12.34" are the same
+// amount of GBP
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum Amount {
+    Minor(i64),
+    Decimal(String),
+}
+
+impl Amount {
+    pub fn minor_units(&self, minor_unit: i32) -> Result<i64, String> {
+        match self {
+            Amount::Minor(v) => Ok(*v),
+            Amount::Decimal(v) => parse_decimal(v, minor_unit),
+        }
+    }
+}
+
+impl From<i32> for Amount {
+    fn from(value: i32) -> Amount {
+        Amount::Minor(i64::from(value))
+    }
+}
+
+impl From<i64> for Amount {
+    fn from(value: i64) -> Amount {
+        Amount::Minor(value)
+    }
+}
+
+// Whole numbers are minor units, anything else has to look like a decimal
+impl FromStr for Amount {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Amount, String> {
+        if let Ok(v) = value.trim().parse() {
+            return Ok(Amount::Minor(v));
+        }
+
+        // The number of decimal places is only known once the currency is
+        parse_decimal(value, crate::currency::data::MAX_MINOR_UNIT)?;
+
+        Ok(Amount::Decimal(String::from(value.trim())))
+    }
+}
+
+impl Serialize for Amount {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Amount::Minor(v) => serializer.serialize_i64(*v),
+            Amount::Decimal(v) => serializer.serialize_str(v),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn money_is_formatted_with_the_minor_unit_of_its_currency() {
+        assert_eq!(Money::new(1234, "GBP", 2).formatted(), "12.34");
+        assert_eq!(Money::new(-5, "GBP", 2).formatted(), "-0.05");
+        assert_eq!(Money::new(1375, "JPY", 0).formatted(), "1375");
+        assert_eq!(Money::new(1000, "BHD", 3).formatted(), "1.000");
+        assert_eq!(Money::new(1234, "GBP", 2).to_string(), "12.34 GBP");
+        assert_eq!(
+            Money::new(i64::MAX, "GBP", 2).formatted(),
+            "92233720368547758.07"
+        );
+    }
+
+    #[test]
+    fn arithmetic_fails_instead_of_overflowing() {
+        assert_eq!(
+            total(vec![3_000_000_000, 4_000_000_000]).unwrap(),
+            7_000_000_000
+        );
+        assert_eq!(sub(0, i64::MAX).unwrap(), -i64::MAX);
+
+        assert_eq!(add(i64::MAX, 1).unwrap_err().code(), "overflow");
+        assert_eq!(sub(i64::MIN, 1).unwrap_err().code(), "overflow");
+        assert_eq!(neg(i64::MIN).unwrap_err().code(), "overflow");
+        assert!(total(vec![i64::MAX, 1, -1]).is_err());
+        assert!(Money::new(i64::MAX, "GBP", 2).checked_add(1).is_err());
+    }
+
+    #[test]
+    fn money_serializes_raw_and_formatted_amounts() {
+        let json = serde_json::to_value(Money::new(-4500, "GBP", 2)).unwrap();
+
+        assert_eq!(
+            json,
+            serde_json::json!({ "amount": -4500, "currency": "GBP", "formatted": "-45.00" })
+        );
+    }
+
+    #[test]
+    fn decimals_are_parsed_into_minor_units() {
+        assert_eq!(parse_decimal("12.34", 2), Ok(1234));
+        assert_eq!(parse_decimal("12.3", 2), Ok(1230));
+        assert_eq!(parse_decimal("12", 2), Ok(1200));
+        assert_eq!(parse_decimal("-0.05", 2), Ok(-5));
+        assert_eq!(parse_decimal("1375", 0), Ok(1375));
+
+        assert!(parse_decimal("12.345", 2).is_err());
+        assert!(parse_decimal("12.5", 0).is_err());
+        assert!(parse_decimal("12.", 2).is_err());
+        assert!(parse_decimal(".5", 2).is_err());
+        assert!(parse_decimal("1,000.00", 2).is_err());
+        assert!(parse_decimal("", 2).is_err());
+        assert!(parse_decimal("99999999999999999999", 2).is_err());
+    }
+
+    #[test]
+    fn request_amounts_are_numbers_or_decimal_strings() {
+        let minor: Amount = serde_json::from_str("1234").unwrap();
+        let decimal: Amount = serde_json::from_str("\"12.34\"").unwrap();
+
+        assert_eq!(minor.minor_units(2), Ok(1234));
+        assert_eq!(decimal.minor_units(2), Ok(1234));
+        assert!(decimal.minor_units(0).is_err());
+
+        assert_eq!("1250".parse(), Ok(Amount::Minor(1250)));
+        assert_eq!("12.50".parse(), Ok(Amount::Decimal(String::from("12.50"))));
+        assert!("twelve".parse::<Amount>().is_err());
+    }
+}
@@ -0,0 +1,548 @@
+use crate::account::data::AccountType;
+use crate::currency::data::Currency;
+use crate::error::{LedgerError, Result};
+use crate::fx::data::convert;
+use crate::money::{self, Money};
+use chrono::{DateTime, Utc};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountTotals {
+    pub id: i32,
+    pub acc_type: AccountType,
+    pub name: String,
+    pub currency: String,
+    pub debits: Money,
+    pub credits: Money,
+    pub balance: Money,
+    pub parent: Option<i32>,
+    // Balance of the account and all of its descendants
+    pub rolled_up_balance: Money,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrialBalanceGroup {
+    pub acc_type: AccountType,
+    pub accounts: Vec<AccountTotals>,
+}
+
+// Column totals. Amounts in different currencies can't be added up,
+// so the columns are totalled separately for every currency
+#[derive(Debug, Serialize, PartialEq)]
+pub struct CurrencyTotal {
+    pub currency: String,
+    pub debits: Money,
+    pub credits: Money,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrialBalance {
+    pub date: DateTime<Utc>,
+    pub groups: Vec<TrialBalanceGroup>,
+    pub totals: Vec<CurrencyTotal>,
+    pub balanced: bool,
+}
+
+impl TrialBalance {
+    // Groups the lines by account type, keeping the order they came in,
+    // and totals the debit and credit columns
+    pub fn new(date: DateTime<Utc>, lines: Vec<AccountTotals>) -> Result<TrialBalance> {
+        let mut groups: Vec<TrialBalanceGroup> = Vec::new();
+        let mut columns: BTreeMap<String, (Money, Money)> = BTreeMap::new();
+
+        for line in lines {
+            let column = columns
+                .entry(line.currency.clone())
+                .or_insert_with(|| (line.debits.with_amount(0), line.credits.with_amount(0)));
+            column.0 = column.0.checked_add(line.debits.amount)?;
+            column.1 = column.1.checked_add(line.credits.amount)?;
+
+            match groups.iter_mut().find(|g| g.acc_type == line.acc_type) {
+                Some(group) => group.accounts.push(line),
+                None => groups.push(TrialBalanceGroup {
+                    acc_type: line.acc_type,
+                    accounts: vec![line],
+                }),
+            }
+        }
+
+        let totals: Vec<CurrencyTotal> = columns
+            .into_iter()
+            .map(|(currency, (debits, credits))| CurrencyTotal {
+                currency,
+                debits,
+                credits,
+            })
+            .collect();
+
+        let balanced = totals.iter().all(|t| t.debits == t.credits);
+
+        Ok(TrialBalance {
+            date,
+            groups,
+            totals,
+            balanced,
+        })
+    }
+}
+
+// Adds up sets of per currency amounts, each multiplied by its sign
+fn combine(parts: &[(&[Money], i64)]) -> Result<Vec<Money>> {
+    let mut sums: BTreeMap<String, Money> = BTreeMap::new();
+
+    for (amounts, sign) in parts {
+        for amount in amounts.iter() {
+            let value = if *sign < 0 {
+                money::neg(amount.amount)?
+            } else {
+                amount.amount
+            };
+            let sum = sums
+                .entry(amount.currency.clone())
+                .or_insert_with(|| amount.with_amount(0));
+
+            *sum = sum.checked_add(value)?;
+        }
+    }
+
+    Ok(sums.into_values().collect())
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct StatementLine {
+    pub id: i32,
+    pub name: String,
+    pub currency: String,
+    pub balance: Money,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StatementSection {
+    pub lines: Vec<StatementLine>,
+    pub subtotals: Vec<Money>,
+}
+
+impl StatementSection {
+    fn new(totals: &[AccountTotals], acc_type: AccountType) -> Result<StatementSection> {
+        let lines: Vec<StatementLine> = totals
+            .iter()
+            .filter(|t| t.acc_type == acc_type)
+            .map(|t| {
+                Ok(StatementLine {
+                    id: t.id,
+                    name: t.name.clone(),
+                    currency: t.currency.clone(),
+                    balance: t.acc_type.normal_money(&t.balance)?,
+                })
+            })
+            .collect::<Result<Vec<StatementLine>>>()?;
+
+        let amounts: Vec<Money> = lines.iter().map(|l| l.balance.clone()).collect();
+
+        Ok(StatementSection {
+            subtotals: combine(&[(&amounts, 1)])?,
+            lines,
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IncomeStatement {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub revenue: StatementSection,
+    pub gains: StatementSection,
+    pub expenses: StatementSection,
+    pub losses: StatementSection,
+    // Revenue + Gains - Expenses - Losses
+    pub net_income: Vec<Money>,
+}
+
+impl IncomeStatement {
+    pub fn new(
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        totals: &[AccountTotals],
+    ) -> Result<IncomeStatement> {
+        let revenue = StatementSection::new(totals, AccountType::Revenue)?;
+        let gains = StatementSection::new(totals, AccountType::Gains)?;
+        let expenses = StatementSection::new(totals, AccountType::Expenses)?;
+        let losses = StatementSection::new(totals, AccountType::Losses)?;
+
+        let net_income = combine(&[
+            (&revenue.subtotals, 1),
+            (&gains.subtotals, 1),
+            (&expenses.subtotals, -1),
+            (&losses.subtotals, -1),
+        ])?;
+
+        Ok(IncomeStatement {
+            from,
+            to,
+            revenue,
+            gains,
+            expenses,
+            losses,
+            net_income,
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BalanceSheet {
+    pub date: DateTime<Utc>,
+    pub assets: StatementSection,
+    pub liabilities: StatementSection,
+    pub equity: StatementSection,
+    // Net income of every transaction up to the date of the balance sheet
+    pub retained_earnings: Vec<Money>,
+    // Liabilities + Equity + Retained earnings
+    pub liabilities_and_equity: Vec<Money>,
+    // Assets = Liabilities + Equity + Retained earnings in every currency
+    pub balanced: bool,
+}
+
+impl BalanceSheet {
+    pub fn new(date: DateTime<Utc>, totals: &[AccountTotals]) -> Result<BalanceSheet> {
+        let assets = StatementSection::new(totals, AccountType::Assets)?;
+        let liabilities = StatementSection::new(totals, AccountType::Liabilities)?;
+        let equity = StatementSection::new(totals, AccountType::Equities)?;
+
+        let retained_earnings = IncomeStatement::new(date, date, totals)?.net_income;
+
+        let liabilities_and_equity = combine(&[
+            (&liabilities.subtotals, 1),
+            (&equity.subtotals, 1),
+            (&retained_earnings, 1),
+        ])?;
+
+        let difference = combine(&[(&assets.subtotals, 1), (&liabilities_and_equity, -1)])?;
+        let balanced = difference.iter().all(|d| d.amount == 0);
+
+        Ok(BalanceSheet {
+            date,
+            assets,
+            liabilities,
+            equity,
+            retained_earnings,
+            liabilities_and_equity,
+            balanced,
+        })
+    }
+}
+
+// The currency a report is converted into
+#[derive(Debug, Deserialize)]
+pub struct CurrencyQuery {
+    pub currency: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NetWorthLine {
+    pub id: i32,
+    pub acc_type: AccountType,
+    pub name: String,
+    // Debits - Credits, so liabilities count against the assets
+    pub balance: Money,
+    // The balance in the reporting currency, None without a rate
+    pub converted: Option<Money>,
+    pub missing_rate: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NetWorthSubtotal {
+    // The unconverted total of every account in the currency
+    pub subtotal: Money,
+    // 1 of the currency in the reporting currency
+    pub rate: Option<f64>,
+    pub converted: Option<Money>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NetWorth {
+    pub date: DateTime<Utc>,
+    pub currency: String,
+    pub accounts: Vec<NetWorthLine>,
+    pub subtotals: Vec<NetWorthSubtotal>,
+    // The converted subtotals added up. Currencies without a rate are left out
+    pub total: Money,
+    // False if any account could not be converted
+    pub complete: bool,
+}
+
+impl NetWorth {
+    // Converts the assets and liabilities into the reporting currency. rates
+    // holds the rate of every other currency as of the date, None if there is
+    // no stored rate. Every currency is converted once, as a subtotal, so the
+    // total is not off by the rounding of every single account
+    pub fn new(
+        date: DateTime<Utc>,
+        reporting: &Currency,
+        totals: &[AccountTotals],
+        rates: &HashMap<String, Option<f64>>,
+    ) -> Result<NetWorth> {
+        let rate = |code: &str| {
+            if code == reporting.code {
+                return Some(1.0);
+            }
+
+            rates.get(code).cloned().flatten()
+        };
+
+        // Amounts in the reporting currency are taken as they are, and
+        // nothing needs a rate to be converted
+        let into_reporting = |amount: &Money| -> Result<Option<Money>> {
+            if amount.currency == reporting.code || amount.amount == 0 {
+                return Ok(Some(Money::new(
+                    amount.amount,
+                    &reporting.code,
+                    reporting.minor_unit,
+                )));
+            }
+
+            match rate(&amount.currency) {
+                Some(r) => {
+                    let value = convert(amount.amount, r, amount.minor_unit, reporting.minor_unit)
+                        .ok_or_else(|| LedgerError::overflow(amount))?;
+
+                    Ok(Some(Money::new(
+                        value,
+                        &reporting.code,
+                        reporting.minor_unit,
+                    )))
+                }
+                None => Ok(None),
+            }
+        };
+
+        let accounts = totals
+            .iter()
+            .filter(|t| matches!(t.acc_type, AccountType::Assets | AccountType::Liabilities))
+            .map(|t| {
+                let converted = into_reporting(&t.balance)?;
+
+                Ok(NetWorthLine {
+                    id: t.id,
+                    acc_type: t.acc_type,
+                    name: t.name.clone(),
+                    balance: t.balance.clone(),
+                    missing_rate: converted.is_none(),
+                    converted,
+                })
+            })
+            .collect::<Result<Vec<NetWorthLine>>>()?;
+
+        let balances: Vec<Money> = accounts.iter().map(|a| a.balance.clone()).collect();
+        let subtotals = combine(&[(&balances, 1)])?
+            .into_iter()
+            .map(|subtotal| {
+                Ok(NetWorthSubtotal {
+                    rate: rate(&subtotal.currency),
+                    converted: into_reporting(&subtotal)?,
+                    subtotal,
+                })
+            })
+            .collect::<Result<Vec<NetWorthSubtotal>>>()?;
+
+        let total = money::total(
+            subtotals
+                .iter()
+                .filter_map(|s| s.converted.as_ref().map(|c| c.amount)),
+        )?;
+
+        Ok(NetWorth {
+            date,
+            currency: reporting.code.clone(),
+            complete: accounts.iter().all(|a| !a.missing_rate),
+            accounts,
+            subtotals,
+            total: Money::new(total, &reporting.code, reporting.minor_unit),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(
+        id: i32,
+        acc_type: AccountType,
+        currency: &str,
+        debits: i64,
+        credits: i64,
+    ) -> AccountTotals {
+        let money = |amount| Money::new(amount, currency, 2);
+
+        AccountTotals {
+            id,
+            acc_type,
+            name: format!("Account {}", id),
+            currency: String::from(currency),
+            debits: money(debits),
+            credits: money(credits),
+            balance: money(debits - credits),
+            parent: None,
+            rolled_up_balance: money(debits - credits),
+        }
+    }
+
+    #[test]
+    fn trial_balance_groups_accounts_by_type() {
+        let lines = vec![
+            line(1, AccountType::Assets, "GBP", 1000, 400),
+            line(2, AccountType::Assets, "GBP", 50, 0),
+            line(3, AccountType::Revenue, "GBP", 0, 1050),
+            line(4, AccountType::Expenses, "GBP", 400, 0),
+        ];
+
+        let report = TrialBalance::new(Utc::now(), lines).unwrap();
+
+        assert_eq!(report.groups.len(), 3);
+        assert_eq!(report.groups[0].acc_type, AccountType::Assets);
+        assert_eq!(report.groups[0].accounts.len(), 2);
+        assert_eq!(report.groups[1].acc_type, AccountType::Revenue);
+        assert_eq!(report.groups[2].acc_type, AccountType::Expenses);
+    }
+
+    #[test]
+    fn trial_balance_totals_columns_per_currency() {
+        let lines = vec![
+            line(1, AccountType::Assets, "GBP", 1000, 400),
+            line(2, AccountType::Expenses, "GBP", 400, 0),
+            line(3, AccountType::Equities, "GBP", 0, 1000),
+            line(4, AccountType::Assets, "EUR", 300, 0),
+            line(5, AccountType::Equities, "EUR", 0, 200),
+        ];
+
+        let report = TrialBalance::new(Utc::now(), lines).unwrap();
+
+        assert_eq!(
+            report.totals,
+            vec![
+                CurrencyTotal {
+                    currency: String::from("EUR"),
+                    debits: Money::new(300, "EUR", 2),
+                    credits: Money::new(200, "EUR", 2),
+                },
+                CurrencyTotal {
+                    currency: String::from("GBP"),
+                    debits: Money::new(1400, "GBP", 2),
+                    credits: Money::new(1400, "GBP", 2),
+                },
+            ]
+        );
+        assert!(!report.balanced);
+    }
+
+    #[test]
+    fn income_statement_nets_revenue_against_expenses() {
+        let totals = vec![
+            line(1, AccountType::Assets, "GBP", 210000, 60000),
+            line(2, AccountType::Revenue, "GBP", 0, 200000),
+            line(3, AccountType::Gains, "GBP", 0, 10000),
+            line(4, AccountType::Expenses, "GBP", 55000, 0),
+            line(5, AccountType::Losses, "GBP", 5000, 0),
+        ];
+
+        let statement = IncomeStatement::new(Utc::now(), Utc::now(), &totals).unwrap();
+
+        assert_eq!(statement.revenue.lines[0].balance.amount, 200000);
+        assert_eq!(statement.expenses.lines[0].balance.amount, 55000);
+        assert_eq!(statement.net_income, vec![Money::new(150000, "GBP", 2)]);
+    }
+
+    #[test]
+    fn balance_sheet_includes_retained_earnings() {
+        let totals = vec![
+            line(1, AccountType::Assets, "GBP", 510000, 60000),
+            line(2, AccountType::Liabilities, "GBP", 10000, 110000),
+            line(3, AccountType::Equities, "GBP", 0, 200000),
+            line(4, AccountType::Revenue, "GBP", 0, 200000),
+            line(5, AccountType::Expenses, "GBP", 60000, 0),
+        ];
+
+        let sheet = BalanceSheet::new(Utc::now(), &totals).unwrap();
+
+        assert_eq!(sheet.assets.subtotals[0].amount, 450000);
+        assert_eq!(sheet.liabilities.subtotals[0].amount, 100000);
+        assert_eq!(sheet.retained_earnings[0].amount, 140000);
+        assert_eq!(sheet.liabilities_and_equity[0].formatted(), "4400.00");
+        assert!(!sheet.balanced);
+    }
+
+    #[test]
+    fn net_worth_converts_every_currency_it_has_a_rate_for() {
+        let gbp = Currency {
+            code: String::from("GBP"),
+            numeric_code: 826,
+            minor_unit: 2,
+            name: String::from("Pound Sterling"),
+        };
+        let totals = vec![
+            line(1, AccountType::Assets, "GBP", 500000, 0),
+            line(2, AccountType::Liabilities, "GBP", 0, 120000),
+            line(3, AccountType::Assets, "EUR", 11500, 0),
+            line(4, AccountType::Assets, "USD", 1000, 0),
+            line(5, AccountType::Assets, "CHF", 0, 0),
+            line(6, AccountType::Expenses, "GBP", 120000, 0),
+        ];
+        let mut rates = HashMap::new();
+        rates.insert(String::from("EUR"), Some(0.8));
+        rates.insert(String::from("USD"), None);
+        rates.insert(String::from("CHF"), None);
+
+        let worth = NetWorth::new(Utc::now(), &gbp, &totals, &rates).unwrap();
+
+        assert_eq!(worth.accounts.len(), 5);
+        assert_eq!(
+            worth.accounts[1].converted,
+            Some(Money::new(-120000, "GBP", 2))
+        );
+        assert_eq!(
+            worth.accounts[2].converted,
+            Some(Money::new(9200, "GBP", 2))
+        );
+        assert!(worth.accounts[3].missing_rate);
+        assert!(!worth.accounts[4].missing_rate);
+
+        let currencies: Vec<&str> = worth
+            .subtotals
+            .iter()
+            .map(|s| s.subtotal.currency.as_str())
+            .collect();
+        assert_eq!(currencies, vec!["CHF", "EUR", "GBP", "USD"]);
+        assert_eq!(worth.subtotals[2].subtotal.amount, 380000);
+        assert_eq!(worth.subtotals[3].converted, None);
+
+        assert_eq!(worth.total.formatted(), "3892.00");
+        assert!(!worth.complete);
+    }
+
+    #[test]
+    fn balance_sheet_of_balanced_books_balances() {
+        let totals = vec![
+            line(1, AccountType::Assets, "GBP", 300000, 50000),
+            line(2, AccountType::Equities, "GBP", 0, 100000),
+            line(3, AccountType::Revenue, "GBP", 0, 200000),
+            line(4, AccountType::Expenses, "GBP", 50000, 0),
+            line(5, AccountType::Assets, "EUR", 1000, 0),
+            line(6, AccountType::Equities, "EUR", 0, 1000),
+        ];
+
+        let sheet = BalanceSheet::new(Utc::now(), &totals).unwrap();
+
+        assert_eq!(sheet.liabilities.lines.len(), 0);
+        assert_eq!(sheet.retained_earnings[0].amount, 150000);
+        assert!(sheet.balanced);
+    }
+}
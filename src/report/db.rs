@@ -0,0 +1,180 @@
+use crate::account::data::rolled_up_balances;
+use crate::error::Result;
+use crate::money::Money;
+use crate::report::data::AccountTotals;
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection};
+
+// Debit and credit totals for every account, counting only the entries of
+// transactions dated on or before `to` and, if given, on or after `from`
+pub fn account_totals(
+    conn: &Connection,
+    from: Option<DateTime<Utc>>,
+    to: DateTime<Utc>,
+) -> Result<Vec<AccountTotals>> {
+    let mut stmt = conn.prepare(
+        "SELECT a.id, a.type, a.name, a.currency,
+        (SELECT ifnull(SUM(d.balance),0) FROM Debits as d INNER JOIN Transactions as t ON d.transaction_id = t.id
+            WHERE d.account = a.id AND julianday(t.date) <= julianday(?1)
+            AND (?2 IS NULL OR julianday(t.date) >= julianday(?2))) as \"debits\",
+        (SELECT ifnull(SUM(c.balance),0) FROM Credits as c INNER JOIN Transactions as t ON c.transaction_id = t.id
+            WHERE c.account = a.id AND julianday(t.date) <= julianday(?1)
+            AND (?2 IS NULL OR julianday(t.date) >= julianday(?2))) as \"credits\",
+        a.parent, cu.minor_unit
+        FROM Accounts as a INNER JOIN Currency as cu ON a.currency = cu.code
+        ORDER BY a.type, a.name",
+    )?;
+
+    let mut lines = stmt
+        .query_map(params![to, from], |row| {
+            let currency: String = row.get(3)?;
+            let minor_unit: i32 = row.get(7)?;
+            let zero = Money::new(0, &currency, minor_unit);
+
+            // The balances are filled in below with checked arithmetic
+            Ok(AccountTotals {
+                id: row.get(0)?,
+                acc_type: row.get(1)?,
+                name: row.get(2)?,
+                debits: zero.with_amount(row.get(4)?),
+                credits: zero.with_amount(row.get(5)?),
+                currency,
+                balance: zero.clone(),
+                parent: row.get(6)?,
+                rolled_up_balance: zero,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<AccountTotals>>>()?;
+
+    for line in lines.iter_mut() {
+        line.balance = line.debits.checked_sub(line.credits.amount)?;
+    }
+
+    let balances: Vec<(i32, Option<i32>, i64)> = lines
+        .iter()
+        .map(|l| (l.id, l.parent, l.balance.amount))
+        .collect();
+    let rolled = rolled_up_balances(&balances)?;
+
+    for line in lines.iter_mut() {
+        line.rolled_up_balance = line.balance.with_amount(rolled[&line.id]);
+    }
+
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::data::AccountType;
+    use chrono::TimeZone;
+    use rusqlite::params;
+
+    fn create_base(conn: &Connection) {
+        conn.execute_batch(
+            "INSERT INTO Accounts (type, name, currency) VALUES
+            (0, 'Current', 'GBP'),
+            (3, 'Salary', 'GBP'),
+            (4, 'Food', 'GBP')",
+        )
+        .unwrap();
+    }
+
+    fn add_transaction(
+        conn: &Connection,
+        date: DateTime<Utc>,
+        debit: i32,
+        credit: i32,
+        balance: i32,
+    ) {
+        conn.execute_batch("BEGIN").unwrap();
+        conn.execute(
+            "INSERT INTO Transactions (date, name) VALUES (?1, 'Test')",
+            params![date],
+        )
+        .unwrap();
+        let id = conn.last_insert_rowid();
+        conn.execute(
+            "INSERT INTO Debits (account, transaction_id, balance) VALUES (?1, ?2, ?3)",
+            params![debit, id, balance],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO Credits (account, transaction_id, balance) VALUES (?1, ?2, ?3)",
+            params![credit, id, balance],
+        )
+        .unwrap();
+        conn.execute_batch("COMMIT").unwrap();
+    }
+
+    #[test]
+    fn trial_balance_lists_every_account_as_of_date() {
+        let pool = crate::migrations::memory_pool();
+        create_base(&pool.get().unwrap());
+
+        let conn = pool.get().unwrap();
+        add_transaction(&conn, Utc.ymd(2020, 1, 1).and_hms(9, 0, 0), 1, 2, 200000);
+        add_transaction(&conn, Utc.ymd(2020, 1, 15).and_hms(18, 0, 0), 3, 1, 4500);
+        add_transaction(&conn, Utc.ymd(2020, 2, 1).and_hms(9, 0, 0), 1, 2, 200000);
+
+        let lines = account_totals(&conn, None, Utc.ymd(2020, 1, 31).and_hms(23, 59, 59)).unwrap();
+
+        assert_eq!(lines.len(), 3);
+
+        let current = lines.iter().find(|l| l.name == "Current").unwrap();
+        assert_eq!(current.debits.amount, 200000);
+        assert_eq!(current.credits.amount, 4500);
+        assert_eq!(current.balance.amount, 195500);
+
+        let food = lines.iter().find(|l| l.name == "Food").unwrap();
+        assert_eq!(food.acc_type, AccountType::Expenses);
+        assert_eq!(food.balance.amount, 4500);
+    }
+
+    #[test]
+    fn account_totals_can_be_limited_to_a_range() {
+        let pool = crate::migrations::memory_pool();
+        create_base(&pool.get().unwrap());
+
+        let conn = pool.get().unwrap();
+        add_transaction(&conn, Utc.ymd(2020, 1, 1).and_hms(9, 0, 0), 1, 2, 200000);
+        add_transaction(&conn, Utc.ymd(2020, 2, 1).and_hms(9, 0, 0), 1, 2, 210000);
+        add_transaction(&conn, Utc.ymd(2020, 3, 1).and_hms(9, 0, 0), 1, 2, 220000);
+
+        let lines = account_totals(
+            &conn,
+            Some(Utc.ymd(2020, 2, 1).and_hms(0, 0, 0)),
+            Utc.ymd(2020, 2, 29).and_hms(23, 59, 59),
+        )
+        .unwrap();
+
+        let salary = lines.iter().find(|l| l.name == "Salary").unwrap();
+        assert_eq!(salary.credits.amount, 210000);
+        assert_eq!(salary.balance.amount, -210000);
+    }
+
+    #[test]
+    fn account_totals_roll_up_child_accounts() {
+        let pool = crate::migrations::memory_pool();
+        create_base(&pool.get().unwrap());
+
+        let conn = pool.get().unwrap();
+        conn.execute(
+            "INSERT INTO Accounts (type, name, currency, parent) VALUES (4, 'Groceries', 'GBP', 3)",
+            params![],
+        )
+        .unwrap();
+        add_transaction(&conn, Utc.ymd(2020, 1, 15).and_hms(18, 0, 0), 3, 1, 500);
+        add_transaction(&conn, Utc.ymd(2020, 1, 16).and_hms(18, 0, 0), 4, 1, 4500);
+
+        let lines = account_totals(&conn, None, Utc.ymd(2020, 12, 31).and_hms(0, 0, 0)).unwrap();
+
+        let food = lines.iter().find(|l| l.name == "Food").unwrap();
+        assert_eq!(food.balance.amount, 500);
+        assert_eq!(food.rolled_up_balance.amount, 5000);
+
+        let groceries = lines.iter().find(|l| l.name == "Groceries").unwrap();
+        assert_eq!(groceries.parent, Some(3));
+        assert_eq!(groceries.rolled_up_balance.amount, 4500);
+    }
+}
@@ -0,0 +1,70 @@
+use actix_web::{web, HttpResponse};
+
+pub mod data;
+pub mod db;
+
+use crate::datastruct;
+use crate::error::{LedgerError, Result};
+use crate::Ledger;
+
+pub async fn trial_balance(
+    query: web::Query<datastruct::AsOfQuery>,
+    ledger: web::Data<Ledger>,
+) -> Result<HttpResponse> {
+    if !query.valid_date() {
+        return Err(LedgerError::Validation(String::from(
+            "date has to be an RFC 3339 timestamp",
+        )));
+    }
+
+    let result = ledger.trial_balance(query.date())?;
+
+    Ok(HttpResponse::Ok().json(result))
+}
+
+pub async fn balance_sheet(
+    query: web::Query<datastruct::AsOfQuery>,
+    ledger: web::Data<Ledger>,
+) -> Result<HttpResponse> {
+    if !query.valid_date() {
+        return Err(LedgerError::Validation(String::from(
+            "date has to be an RFC 3339 timestamp",
+        )));
+    }
+
+    let result = ledger.balance_sheet(query.date())?;
+
+    Ok(HttpResponse::Ok().json(result))
+}
+
+// Assets and liabilities converted into the currency given in the query
+pub async fn net_worth(
+    query: web::Query<datastruct::AsOfQuery>,
+    currency: web::Query<data::CurrencyQuery>,
+    ledger: web::Data<Ledger>,
+) -> Result<HttpResponse> {
+    if !query.valid_date() {
+        return Err(LedgerError::Validation(String::from(
+            "date has to be an RFC 3339 timestamp",
+        )));
+    }
+
+    let result = ledger.net_worth(&currency.currency, query.date())?;
+
+    Ok(HttpResponse::Ok().json(result))
+}
+
+pub async fn income_statement(
+    query: web::Query<datastruct::RangeQuery>,
+    ledger: web::Data<Ledger>,
+) -> Result<HttpResponse> {
+    if !query.valid_range() {
+        return Err(LedgerError::Validation(String::from(
+            "from and to have to be RFC 3339 timestamps with from before to",
+        )));
+    }
+
+    let result = ledger.income_statement(query.from().unwrap(), query.to())?;
+
+    Ok(HttpResponse::Ok().json(result))
+}
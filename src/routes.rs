@@ -0,0 +1,141 @@
+use actix_web::web;
+
+use crate::{account, api, budget, currency, fx, report, transaction};
+
+// Every endpoint of the HTTP API. The handlers expect the Ledger as app data
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/").route(web::get().to(account::list_accounts)))
+        .service(
+            web::scope("/currencies")
+                .service(
+                    web::resource("")
+                        .route(web::get().to(currency::list_currencies))
+                        .route(web::post().to(currency::create_currency)),
+                )
+                .service(
+                    web::resource("/import").route(web::post().to(currency::import_currencies)),
+                )
+                .service(
+                    web::resource("/{code}")
+                        .route(web::get().to(currency::get_currency))
+                        .route(web::put().to(currency::update_currency))
+                        .route(web::delete().to(currency::delete_currency)),
+                ),
+        )
+        .service(
+            web::scope("/exchange-rates")
+                .service(
+                    web::resource("")
+                        .route(web::get().to(fx::list_exchange_rates))
+                        .route(web::post().to(fx::create_exchange_rate)),
+                )
+                .service(web::resource("/import").route(web::post().to(fx::import_exchange_rates)))
+                .service(
+                    web::resource("/{id}")
+                        .route(web::get().to(fx::get_exchange_rate))
+                        .route(web::put().to(fx::update_exchange_rate))
+                        .route(web::delete().to(fx::delete_exchange_rate)),
+                ),
+        )
+        .service(
+            web::scope("/fx-accounts")
+                .service(web::resource("").route(web::get().to(fx::list_fx_accounts)))
+                .service(
+                    web::resource("/{code}")
+                        .route(web::put().to(fx::set_fx_account))
+                        .route(web::delete().to(fx::delete_fx_account)),
+                ),
+        )
+        .service(web::resource("/integrity").route(web::get().to(api::check_ledger_integrity)))
+        .service(
+            web::scope("/transactions")
+                .service(
+                    web::resource("")
+                        .route(web::get().to(transaction::list_transactions))
+                        .route(web::post().to(transaction::create_transaction)),
+                )
+                .service(
+                    web::resource("/{id}")
+                        .route(web::get().to(transaction::get_transaction))
+                        .route(web::put().to(transaction::update_transaction))
+                        .route(web::delete().to(transaction::delete_transaction)),
+                )
+                .service(
+                    web::resource("/{id}/void")
+                        .route(web::post().to(transaction::void_transaction)),
+                ),
+        )
+        .service(
+            web::scope("/account")
+                .service(web::resource("/").route(web::post().to(account::create_account)))
+                .service(
+                    web::resource("/{id}")
+                        .route(web::get().to(account::get_account))
+                        .route(web::put().to(account::update_account))
+                        .route(web::delete().to(account::delete_account)),
+                )
+                .service(
+                    web::resource("/{id}/archive").route(web::post().to(account::archive_account)),
+                )
+                .service(
+                    web::resource("/{id}/reopen").route(web::post().to(account::reopen_account)),
+                )
+                .service(
+                    web::resource("/{id}/balance").route(web::get().to(api::get_account_balance)),
+                )
+                .service(
+                    web::resource("/{id}/entries")
+                        .route(web::get().to(account::list_account_entries)),
+                )
+                .service(
+                    web::resource("/{id}/balance/history")
+                        .route(web::get().to(api::get_account_balance_history)),
+                ),
+        )
+        .service(
+            web::scope("/accounts")
+                .service(web::resource("").route(web::get().to(account::search_accounts)))
+                .service(web::resource("/tree").route(web::get().to(account::list_account_tree)))
+                .service(web::resource("/asset").route(web::get().to(account::list_asset_accounts)))
+                .service(
+                    web::resource("/expense").route(web::get().to(account::list_expense_accounts)),
+                ),
+        )
+        .service(
+            web::scope("/reports")
+                .service(
+                    web::resource("/trial-balance").route(web::get().to(report::trial_balance)),
+                )
+                .service(
+                    web::resource("/balance-sheet").route(web::get().to(report::balance_sheet)),
+                )
+                .service(
+                    web::resource("/income-statement")
+                        .route(web::get().to(report::income_statement)),
+                )
+                .service(web::resource("/net-worth").route(web::get().to(report::net_worth))),
+        )
+        .service(
+            web::scope("/budget")
+                .service(
+                    web::resource("")
+                        .route(web::get().to(budget::get_current_budget))
+                        .route(web::post().to(budget::create_budget)),
+                )
+                .service(web::resource("/generate").route(web::post().to(budget::generate_budget)))
+                .service(
+                    web::scope("/{id}")
+                        .service(
+                            web::resource("")
+                                .route(web::get().to(budget::get_budget))
+                                .route(web::delete().to(budget::delete_budget)),
+                        )
+                        .service(
+                            web::resource("/entry")
+                                .route(web::post().to(budget::add_entry_to_budget))
+                                .route(web::put().to(budget::update_entry_in_budget))
+                                .route(web::delete().to(budget::delete_entry_in_budget)),
+                        ),
+                ),
+        );
+}
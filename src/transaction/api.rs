@@ -1,163 +1,107 @@
 use crate::datastruct;
-use actix_web::{web, Error, HttpResponse};
-use r2d2::Pool;
-use r2d2_sqlite::SqliteConnectionManager;
+use actix_web::{web, HttpResponse};
 use serde_json::json;
 
-use crate::account;
+use crate::error::{LedgerError, Result};
 use crate::transaction::data;
-use crate::transaction::db;
+use crate::Ledger;
 
 // Get a single transaction
 pub async fn get_transaction(
     params: web::Path<datastruct::IdRequest>,
-    pool: web::Data<Pool<SqliteConnectionManager>>,
-) -> Result<HttpResponse, Error> {
-    let transaction = db::get_transaction(pool.get().unwrap(), params.id);
-
-    match transaction {
-        Ok(response) => Ok(HttpResponse::Ok().json(response)),
-        Err(err) => match err {
-            rusqlite::Error::QueryReturnedNoRows => Ok(HttpResponse::NotFound().finish()),
-            _ => Ok(HttpResponse::InternalServerError().finish()),
-        },
-    }
+    ledger: web::Data<Ledger>,
+) -> Result<HttpResponse> {
+    let transaction = ledger.transaction(params.id)?;
+
+    Ok(HttpResponse::Ok().json(transaction))
 }
 
 // Create a new transaction
 pub async fn create_transaction(
     transaction: web::Json<data::NewTransaction>,
-    pool: web::Data<Pool<SqliteConnectionManager>>,
-) -> Result<HttpResponse, Error> {
-    // First we get the two accounts
-    let from_acc_query = account::db::get_account(pool.get().unwrap(), transaction.from);
-    let to_acc_query = account::db::get_account(pool.get().unwrap(), transaction.to);
-
-    if from_acc_query.is_err() || to_acc_query.is_err() {
-        return Ok(HttpResponse::BadRequest().finish());
-    }
-
-    let from_account = from_acc_query.unwrap();
-    let to_account = to_acc_query.unwrap();
+    ledger: web::Data<Ledger>,
+) -> Result<HttpResponse> {
+    let result = ledger.create_transaction(&transaction)?;
 
-    if !from_account.currency_compatible(&to_account) {
-        return Ok(HttpResponse::BadRequest().finish());
-    }
-
-    let result = db::create_transaction(
-        pool.get().unwrap(),
-        to_account.id,
-        from_account.id,
-        transaction.balance,
-        &transaction.name,
-    );
-
-    match result {
-        Ok(v) => {
-            let result = json!({
-                "status": "CREATED",
-                "id": v,
-            });
-
-            Ok(HttpResponse::Ok().json(result))
-        }
-        Err(_e) => Ok(HttpResponse::InternalServerError().finish()),
-    }
+    Ok(HttpResponse::Ok().json(result))
 }
 
-// Update Transaction name or balance
+// Replace the name, entries and optionally the date of a transaction
 pub async fn update_transaction(
     params: web::Path<datastruct::IdRequest>,
     transaction: web::Json<data::UpdateTransaction>,
-    pool: web::Data<Pool<SqliteConnectionManager>>,
-) -> Result<HttpResponse, Error> {
-    let result = db::update_transaction(
-        pool.get().unwrap(),
-        params.id,
-        transaction.balance,
-        &transaction.name,
-    );
-
-    match result {
-        Ok(()) => {
-            let result = json!({
-                "status": "UPDATED",
-                "id": params.id,
-            });
-
-            Ok(HttpResponse::Ok().json(result))
-        }
-        Err(_e) => Ok(HttpResponse::InternalServerError().finish()),
-    }
+    ledger: web::Data<Ledger>,
+) -> Result<HttpResponse> {
+    let result = ledger.update_transaction(params.id, &transaction)?;
+
+    Ok(HttpResponse::Ok().json(result))
+}
+
+// Void a transaction by recording a reversal of it
+pub async fn void_transaction(
+    params: web::Path<datastruct::IdRequest>,
+    ledger: web::Data<Ledger>,
+) -> Result<HttpResponse> {
+    let result = ledger.void_transaction(params.id)?;
+
+    Ok(HttpResponse::Created().json(result))
 }
 
 // Delete a single transaction
+// Voided pairs are kept for the audit trail and can not be deleted
 pub async fn delete_transaction(
     params: web::Path<datastruct::IdRequest>,
-    pool: web::Data<Pool<SqliteConnectionManager>>,
-) -> Result<HttpResponse, Error> {
-    let result = db::remove_transaction(pool.get().unwrap(), params.id);
-
-    match result {
-        Ok(_v) => {
-            let result = json!({
-                "status": "DELETED",
-                "id": params.id,
-            });
-
-            Ok(HttpResponse::Ok().json(result))
-        }
-        Err(_e) => Ok(HttpResponse::InternalServerError().finish()),
-    }
+    ledger: web::Data<Ledger>,
+) -> Result<HttpResponse> {
+    ledger.delete_transaction(params.id)?;
+
+    let result = json!({
+        "status": "DELETED",
+        "id": params.id,
+    });
+
+    Ok(HttpResponse::Ok().json(result))
 }
 
 // List operations
 // Get all transactions limited to 32
 pub async fn list_transactions(
     query: web::Query<data::DateQuery>,
-    pool: web::Data<Pool<SqliteConnectionManager>>,
-) -> Result<HttpResponse, Error> {
+    ledger: web::Data<Ledger>,
+) -> Result<HttpResponse> {
+    // Specifying only a month is invalid
+    if query.only_month() {
+        return Err(LedgerError::Validation(String::from(
+            "month can only be used together with year",
+        )));
+    }
+
+    if !query.valid_date() {
+        return Err(LedgerError::Validation(String::from(
+            "year has to be 1970 or later and month between 1 and 12",
+        )));
+    }
+
     // If query is full we tackle the full query
     if query.is_full() {
-        if !query.valid_date() {
-            return Ok(HttpResponse::BadRequest().finish());
-        }
-
-        let date_result = db::list_transactions_date(
-            pool.get().unwrap(),
+        let date_result = ledger.transactions_in_month(
             query.year().unwrap(),
             query.month().unwrap(),
-        );
+            query.include_voided(),
+        )?;
 
-        match date_result {
-            Ok(v) => return Ok(HttpResponse::Ok().json(v)),
-            Err(_e) => return Ok(HttpResponse::InternalServerError().finish()),
-        }
+        return Ok(HttpResponse::Ok().json(date_result));
     }
 
     if query.only_year() {
-        if !query.valid_date() {
-            return Ok(HttpResponse::BadRequest().finish());
-        }
-
-        let year_result = db::list_transactions_year(pool.get().unwrap(), query.year().unwrap());
+        let year_result =
+            ledger.transactions_in_year(query.year().unwrap(), query.include_voided())?;
 
-        match year_result {
-            Ok(v) => return Ok(HttpResponse::Ok().json(v)),
-            Err(_e) => return Ok(HttpResponse::InternalServerError().finish()),
-        }
-    }
-
-    // Specifying only a month is invalid
-    if query.only_month() {
-        return Ok(HttpResponse::BadRequest().finish());
+        return Ok(HttpResponse::Ok().json(year_result));
     }
 
     // If the query is empty we just list the transactions
-    let result = db::list_transactions(pool.get().unwrap());
+    let result = ledger.transactions(query.include_voided())?;
 
-    match result {
-        Ok(v) => Ok(HttpResponse::Ok().json(v)),
-        Err(_e) => Ok(HttpResponse::InternalServerError().finish()),
-    }
+    Ok(HttpResponse::Ok().json(result))
 }
@@ -1,5 +1,9 @@
-use chrono::Utc;
+use crate::datastruct::parse_date;
+use crate::money::{self, Amount, Money};
+use chrono::{DateTime, Utc};
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
 use serde_derive::{Deserialize, Serialize};
+use std::convert::TryFrom;
 
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -7,6 +11,8 @@ pub struct Transaction {
     pub id: i32,
     pub date: chrono::DateTime<Utc>,
     pub name: String,
+    pub voided: bool,
+    pub reverses: Option<i32>,
 }
 
 #[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Copy, Clone)]
@@ -17,15 +23,40 @@ pub enum EntryType {
 }
 
 impl EntryType {
-    pub fn from_i32(value: i32) -> EntryType {
+    pub fn from_i32(value: i32) -> Option<EntryType> {
         match value {
-            0 => EntryType::Credit,
-            1 => EntryType::Debit,
-            _ => panic!("Unknown value: {}", value),
+            0 => Some(EntryType::Credit),
+            1 => Some(EntryType::Debit),
+            _ => None,
+        }
+    }
+
+    pub fn to_i32(self) -> i32 {
+        match self {
+            EntryType::Credit => 0,
+            EntryType::Debit => 1,
         }
     }
 }
 
+// An unknown stored number is returned as an out of range error
+impl FromSql for EntryType {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<EntryType> {
+        let raw = value.as_i64()?;
+
+        i32::try_from(raw)
+            .ok()
+            .and_then(EntryType::from_i32)
+            .ok_or(FromSqlError::OutOfRange(raw))
+    }
+}
+
+impl ToSql for EntryType {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.to_i32()))
+    }
+}
+
 #[derive(Debug, Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Entry {
@@ -33,22 +64,102 @@ pub struct Entry {
     pub account: i32,
     pub account_name: String,
     pub transaction_id: i32,
-    pub balance: i32,
+    pub balance: i64,
     pub entry_type: EntryType,
 }
 
+// A single leg of a new transaction. The balance is given in minor units or
+// as a decimal string in the currency of the account
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NewEntry {
+    pub account: i32,
+    pub balance: Amount,
+}
+
+// A leg with its balance in minor units, as it is stored
+#[derive(Debug, Clone, PartialEq)]
+pub struct Posting {
+    pub account: i32,
+    pub balance: i64,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct NewTransaction {
     pub name: String,
-    pub balance: i32,
-    pub from: i32,
-    pub to: i32,
+    pub date: Option<String>,
+    pub debits: Vec<NewEntry>,
+    pub credits: Vec<NewEntry>,
+}
+
+// Returns true if both sides have at least one leg and every leg is positive
+pub fn legs_positive(debits: &[Posting], credits: &[Posting]) -> bool {
+    if debits.is_empty() || credits.is_empty() {
+        return false;
+    }
+
+    debits
+        .iter()
+        .chain(credits.iter())
+        .all(|entry| entry.balance > 0)
+}
+
+// Returns true if both sides have at least one positive leg
+// and the debits add up to the credits. Sides that add up to more
+// than fits into 64 bits are never balanced
+pub fn legs_balanced(debits: &[Posting], credits: &[Posting]) -> bool {
+    if !legs_positive(debits, credits) {
+        return false;
+    }
+
+    let debit_total = money::total(debits.iter().map(|e| e.balance));
+    let credit_total = money::total(credits.iter().map(|e| e.balance));
+
+    match (debit_total, credit_total) {
+        (Ok(d), Ok(c)) => d == c,
+        _ => false,
+    }
+}
+
+impl NewTransaction {
+    // Returns true if no date was supplied or the supplied one is valid
+    pub fn valid_date(&self) -> bool {
+        match &self.date {
+            Some(v) => parse_date(v).is_some(),
+            None => true,
+        }
+    }
+
+    // The date of the transaction. Defaults to now when not supplied
+    pub fn date(&self) -> DateTime<Utc> {
+        self.date
+            .as_ref()
+            .and_then(|v| parse_date(v))
+            .unwrap_or_else(Utc::now)
+    }
 }
 
+// Replaces the name, entries and optionally the date of a transaction
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UpdateTransaction {
     pub name: String,
-    pub balance: i32,
+    pub date: Option<String>,
+    pub debits: Vec<NewEntry>,
+    pub credits: Vec<NewEntry>,
+}
+
+impl UpdateTransaction {
+    // Returns true if no date was supplied or the supplied one is valid
+    pub fn valid_date(&self) -> bool {
+        match &self.date {
+            Some(v) => parse_date(v).is_some(),
+            None => true,
+        }
+    }
+
+    // The new date of the transaction. None leaves the date unchanged
+    pub fn date(&self) -> Option<DateTime<Utc>> {
+        self.date.as_ref().and_then(|v| parse_date(v))
+    }
 }
 
 #[derive(Debug, Serialize, Clone)]
@@ -58,7 +169,7 @@ pub struct EntryV2 {
     account: i32,
     account_name: String,
     transaction_id: i32,
-    balance: i32,
+    balance: Money,
     entry_type: EntryType,
 }
 
@@ -68,7 +179,7 @@ impl EntryV2 {
         account: i32,
         account_name: String,
         transaction_id: i32,
-        balance: i32,
+        balance: Money,
         entry_type: EntryType,
     ) -> EntryV2 {
         EntryV2 {
@@ -97,8 +208,8 @@ impl EntryV2 {
         self.transaction_id
     }
 
-    pub fn balance(&self) -> i32 {
-        self.balance
+    pub fn balance(&self) -> &Money {
+        &self.balance
     }
 
     pub fn entry_type(&self) -> EntryType {
@@ -112,6 +223,11 @@ pub struct TransactionV2 {
     id: i32,
     date: chrono::DateTime<Utc>,
     name: String,
+    voided: bool,
+    // The reversal transaction that voided this one
+    voided_by: Option<i32>,
+    // The original transaction if this one is a reversal
+    reverses: Option<i32>,
     entries: Vec<EntryV2>,
 }
 
@@ -120,13 +236,18 @@ impl TransactionV2 {
         id: i32,
         date: chrono::DateTime<Utc>,
         name: String,
+        voided_by: Option<i32>,
+        reverses: Option<i32>,
         entries: Vec<EntryV2>,
     ) -> TransactionV2 {
         TransactionV2 {
-            id: id,
-            date: date,
-            name: name,
-            entries: entries,
+            id,
+            date,
+            name,
+            voided: voided_by.is_some(),
+            voided_by,
+            reverses,
+            entries,
         }
     }
 
@@ -142,15 +263,36 @@ impl TransactionV2 {
     pub fn entries(&self) -> &[EntryV2] {
         &self.entries
     }
+    pub fn voided(&self) -> bool {
+        self.voided
+    }
+    pub fn voided_by(&self) -> Option<i32> {
+        self.voided_by
+    }
+    pub fn reverses(&self) -> Option<i32> {
+        self.reverses
+    }
+    // Voided transactions and reversals are part of the audit trail
+    // and can no longer be changed
+    pub fn locked(&self) -> bool {
+        self.voided || self.reverses.is_some()
+    }
 }
 
 #[derive(Debug, Deserialize)]
 pub struct DateQuery {
     year: Option<i32>,
     month: Option<u8>,
+    voided: Option<bool>,
 }
 
 impl DateQuery {
+    // Voided transactions and their reversals are listed unless
+    // explicitly hidden with voided=false
+    pub fn include_voided(&self) -> bool {
+        self.voided.unwrap_or(true)
+    }
+
     pub fn year(&self) -> Option<i32> {
         self.year
     }
@@ -216,13 +358,194 @@ impl DateQuery {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use chrono::TimeZone;
+
+    // Every leg resolved in a currency with two decimal places
+    fn postings(legs: &[NewEntry]) -> Vec<Posting> {
+        legs.iter()
+            .map(|leg| Posting {
+                account: leg.account,
+                balance: leg.balance.minor_units(2).unwrap(),
+            })
+            .collect()
+    }
+
+    fn balanced(debits: &[NewEntry], credits: &[NewEntry]) -> bool {
+        legs_balanced(&postings(debits), &postings(credits))
+    }
+
+    fn positive(debits: &[NewEntry], credits: &[NewEntry]) -> bool {
+        legs_positive(&postings(debits), &postings(credits))
+    }
 
     #[test]
     fn entry_correctly_types() {
         let debit = EntryType::from_i32(1);
         let credit = EntryType::from_i32(0);
 
-        assert_eq!(debit, EntryType::Debit);
-        assert_eq!(credit, EntryType::Credit)
+        assert_eq!(debit, Some(EntryType::Debit));
+        assert_eq!(credit, Some(EntryType::Credit));
+        assert_eq!(EntryType::from_i32(2), None);
+    }
+
+    #[test]
+    fn split_transaction_is_balanced() {
+        let transaction = NewTransaction {
+            name: String::from("Weekly shop"),
+            date: None,
+            debits: vec![
+                NewEntry {
+                    account: 2,
+                    balance: Amount::from(3000),
+                },
+                NewEntry {
+                    account: 3,
+                    balance: Amount::from(1500),
+                },
+            ],
+            credits: vec![NewEntry {
+                account: 1,
+                balance: Amount::from(4500),
+            }],
+        };
+
+        assert!(balanced(&transaction.debits, &transaction.credits));
+    }
+
+    #[test]
+    fn unbalanced_or_empty_transaction_is_rejected() {
+        let unbalanced = NewTransaction {
+            name: String::from("Broken"),
+            date: None,
+            debits: vec![NewEntry {
+                account: 2,
+                balance: Amount::from(100),
+            }],
+            credits: vec![NewEntry {
+                account: 1,
+                balance: Amount::from(99),
+            }],
+        };
+        let one_sided = NewTransaction {
+            name: String::from("One sided"),
+            date: None,
+            debits: vec![NewEntry {
+                account: 2,
+                balance: Amount::from(100),
+            }],
+            credits: vec![],
+        };
+        let negative = NewTransaction {
+            name: String::from("Negative"),
+            date: None,
+            debits: vec![
+                NewEntry {
+                    account: 2,
+                    balance: Amount::from(200),
+                },
+                NewEntry {
+                    account: 3,
+                    balance: Amount::from(-100),
+                },
+            ],
+            credits: vec![NewEntry {
+                account: 1,
+                balance: Amount::from(100),
+            }],
+        };
+
+        assert!(!balanced(&unbalanced.debits, &unbalanced.credits));
+        assert!(!balanced(&one_sided.debits, &one_sided.credits));
+        assert!(!balanced(&negative.debits, &negative.credits));
+
+        // Legs in different currencies only have to be positive here
+        assert!(positive(&unbalanced.debits, &unbalanced.credits));
+        assert!(!positive(&one_sided.debits, &one_sided.credits));
+        assert!(!positive(&negative.debits, &negative.credits));
+    }
+
+    #[test]
+    fn transaction_date_is_parsed_or_defaults_to_now() {
+        let backdated = NewTransaction {
+            name: String::from("Card payment"),
+            date: Some(String::from("2020-01-31T18:30:00+01:00")),
+            debits: vec![],
+            credits: vec![],
+        };
+        let undated = NewTransaction {
+            name: String::from("Card payment"),
+            date: None,
+            debits: vec![],
+            credits: vec![],
+        };
+
+        assert!(backdated.valid_date());
+        assert_eq!(backdated.date(), Utc.ymd(2020, 1, 31).and_hms(17, 30, 0));
+        assert!(undated.valid_date());
+        assert!(Utc::now() - undated.date() < chrono::Duration::seconds(5));
+    }
+
+    #[test]
+    fn invalid_transaction_dates_are_rejected() {
+        let garbage = UpdateTransaction {
+            name: String::from("Card payment"),
+            date: Some(String::from("31/01/2020")),
+            debits: vec![],
+            credits: vec![],
+        };
+        let too_old = UpdateTransaction {
+            name: String::from("Card payment"),
+            date: Some(String::from("1969-07-20T20:17:00Z")),
+            debits: vec![],
+            credits: vec![],
+        };
+        let unchanged = UpdateTransaction {
+            name: String::from("Card payment"),
+            date: None,
+            debits: vec![],
+            credits: vec![],
+        };
+
+        assert!(!garbage.valid_date());
+        assert!(!too_old.valid_date());
+        assert!(unchanged.valid_date());
+        assert_eq!(unchanged.date(), None);
+    }
+
+    #[test]
+    fn update_moving_a_leg_must_stay_balanced() {
+        let moved = UpdateTransaction {
+            name: String::from("Weekly shop"),
+            date: None,
+            debits: vec![
+                NewEntry {
+                    account: 3,
+                    balance: Amount::from(2000),
+                },
+                NewEntry {
+                    account: 4,
+                    balance: Amount::from(500),
+                },
+            ],
+            credits: vec![NewEntry {
+                account: 1,
+                balance: Amount::from(2500),
+            }],
+        };
+        let unbalanced = UpdateTransaction {
+            name: String::from("Weekly shop"),
+            date: None,
+            debits: vec![NewEntry {
+                account: 3,
+                balance: Amount::from(2000),
+            }],
+            credits: vec![NewEntry {
+                account: 1,
+                balance: Amount::from(2500),
+            }],
+        };
+
+        assert!(balanced(&moved.debits, &moved.credits));
+        assert!(!balanced(&unbalanced.debits, &unbalanced.credits));
     }
 }
@@ -1,64 +1,155 @@
-use crate::transaction::data::{Entry, EntryType, EntryV2, Transaction, TransactionV2};
-use rusqlite::{params, Result, NO_PARAMS};
+use crate::error::{missing, LedgerError, Result};
+use crate::money::Money;
+use crate::transaction::data::{Entry, EntryV2, Posting, Transaction, TransactionV2};
+use rusqlite::{params, Connection, TransactionBehavior};
 
 use chrono::{DateTime, Utc};
-use std::ops::DerefMut;
 
 // Single transactions functions
 
-pub fn get_transaction(
-    conn: r2d2::PooledConnection<r2d2_sqlite::SqliteConnectionManager>,
-    id: i32,
-) -> Result<TransactionV2> {
-    let mut stmt = conn.prepare("SELECT id, date, name from Transactions WHERE id = ?1")?;
+pub fn get_transaction(conn: &Connection, id: i32) -> Result<TransactionV2> {
+    let mut stmt = conn.prepare(
+        "SELECT t.id, t.date, t.name, (SELECT r.id FROM Transactions as r WHERE r.reverses = t.id), t.reverses
+        FROM Transactions as t WHERE t.id = ?1",
+    )?;
 
-    let metadata = stmt.query_row(params![id], |row| {
-        Ok((row.get(0)?, row.get(1)?, row.get(2)?))
-    })?;
+    let metadata = stmt
+        .query_row(params![id], |row| {
+            Ok((
+                row.get(0)?,
+                row.get(1)?,
+                row.get(2)?,
+                row.get(3)?,
+                row.get(4)?,
+            ))
+        })
+        .map_err(|e| missing(e, "Transaction", id))?;
 
     let mut entry_stmt = conn.prepare(
         "
-        SELECT c.id, c.account, a.name, c.transaction_id, c.balance, 0 as entry_type FROM Credits as c INNER JOIN Accounts as a ON c.account = a.id WHERE c.transaction_id = ?1
+        SELECT c.id, c.account, a.name, c.transaction_id, c.balance, 0 as entry_type, a.currency, cu.minor_unit FROM Credits as c INNER JOIN Accounts as a ON c.account = a.id INNER JOIN Currency as cu ON a.currency = cu.code WHERE c.transaction_id = ?1
         UNION ALL
-        SELECT d.id, d.account, a.name, d.transaction_id, d.balance, 1 as entry_type FROM Debits as d INNER JOIN Accounts as a ON d.account = a.id WHERE d.transaction_id = ?1",
+        SELECT d.id, d.account, a.name, d.transaction_id, d.balance, 1 as entry_type, a.currency, cu.minor_unit FROM Debits as d INNER JOIN Accounts as a ON d.account = a.id INNER JOIN Currency as cu ON a.currency = cu.code WHERE d.transaction_id = ?1",
     )?;
 
     let entries = entry_stmt
         .query_map(params![id], |row| {
+            let currency: String = row.get(6)?;
+
             Ok(EntryV2::new(
                 row.get(0)?,
                 row.get(1)?,
                 row.get(2)?,
                 row.get(3)?,
-                row.get(4)?,
-                EntryType::from_i32(row.get(5)?),
+                Money::new(row.get(4)?, &currency, row.get(7)?),
+                row.get(5)?,
             ))
-        })
-        .and_then(|mapped_rows| {
-            Ok(mapped_rows
-                .map(|row| row.unwrap())
-                .collect::<Vec<EntryV2>>())
-        })?;
-
-    if entries.len() % 2 != 0 {
-        panic!("Uneven number of entries. Integrity damaged");
-    }
+        })?
+        .collect::<rusqlite::Result<Vec<EntryV2>>>()?;
 
     Ok(TransactionV2::new(
-        metadata.0, metadata.1, metadata.2, entries,
+        metadata.0, metadata.1, metadata.2, metadata.3, metadata.4, entries,
     ))
 }
 
+// The ledger constraints use a deferred foreign key to reject a commit that
+// leaves a transaction unbalanced or mixing currencies
+fn commit_entries(tx: rusqlite::Transaction, transaction_id: i64) -> Result<()> {
+    check_totals(&tx, transaction_id)?;
+
+    tx.commit().map_err(|e| match e {
+        rusqlite::Error::SqliteFailure(f, _)
+            if f.extended_code == rusqlite::ffi::SQLITE_CONSTRAINT_FOREIGNKEY =>
+        {
+            LedgerError::Integrity(format!(
+                "Transaction {} would be unbalanced in one of its currencies",
+                transaction_id
+            ))
+        }
+        e => LedgerError::from(e),
+    })
+}
+
+// Sums the debits and credits of every account the transaction touches. SUM
+// fails once a total is beyond i64, which keeps every stored balance readable
+fn check_totals(tx: &rusqlite::Transaction, transaction_id: i64) -> Result<()> {
+    let totals = tx.query_row(
+        "SELECT MAX(total) FROM
+            (SELECT SUM(balance) as total FROM Debits
+            WHERE account IN (SELECT account FROM Debits WHERE transaction_id = ?1)
+            GROUP BY account
+            UNION ALL
+            SELECT SUM(balance) as total FROM Credits
+            WHERE account IN (SELECT account FROM Credits WHERE transaction_id = ?1)
+            GROUP BY account)",
+        params![transaction_id],
+        |row| row.get::<_, Option<i64>>(0),
+    );
+
+    match totals.map_err(LedgerError::from) {
+        Err(LedgerError::Overflow(_)) => Err(LedgerError::Overflow(format!(
+            "Transaction {} would take an account total beyond the largest amount that can be stored",
+            transaction_id
+        ))),
+        other => other.map(|_| ()),
+    }
+}
+
+// Fails if the transaction does not exist or is part of a voided pair and
+// returns its name otherwise. Callers run it inside an immediate database
+// transaction, so a concurrent void can not slip in before their write
+fn check_unlocked(tx: &rusqlite::Transaction, id: i32) -> Result<Option<String>> {
+    let (name, locked): (Option<String>, bool) = tx
+        .query_row(
+            "SELECT t.name, t.reverses IS NOT NULL OR EXISTS (SELECT 1 FROM Transactions as r WHERE r.reverses = t.id)
+            FROM Transactions as t WHERE t.id = ?1",
+            params![id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .map_err(|e| missing(e, "Transaction", id))?;
+
+    if locked {
+        return Err(LedgerError::Conflict(format!(
+            "Transaction {} is part of a voided pair and can not be changed",
+            id
+        )));
+    }
+
+    Ok(name)
+}
+
+fn insert_entries(
+    tx: &rusqlite::Transaction,
+    transaction_id: i64,
+    debits: &[Posting],
+    credits: &[Posting],
+) -> Result<()> {
+    for debit in debits {
+        tx.execute(
+            "INSERT INTO Debits (account, transaction_id, balance) VALUES (?1, ?2, ?3)",
+            params![debit.account, transaction_id, debit.balance],
+        )?;
+    }
+
+    for credit in credits {
+        tx.execute(
+            "INSERT INTO Credits (account, transaction_id, balance) VALUES (?1, ?2, ?3)",
+            params![credit.account, transaction_id, credit.balance],
+        )?;
+    }
+
+    Ok(())
+}
+
+// Inserts the transaction and all of its legs in a single database transaction
 pub fn create_transaction(
-    mut conn: r2d2::PooledConnection<r2d2_sqlite::SqliteConnectionManager>,
-    debit_account: i32,
-    credit_account: i32,
-    balance: i32,
+    conn: &mut Connection,
     name: &str,
-) -> Result<i64> {
-    let con = conn.deref_mut();
-    let tx = con.transaction()?;
-    let date: DateTime<Utc> = Utc::now();
+    date: DateTime<Utc>,
+    debits: &[Posting],
+    credits: &[Posting],
+) -> Result<TransactionV2> {
+    let tx = conn.transaction()?;
 
     tx.execute(
         "INSERT INTO Transactions (date, name) VALUES (?1, ?2)",
@@ -67,97 +158,128 @@ pub fn create_transaction(
 
     let transaction_id = tx.last_insert_rowid();
 
-    tx.execute(
-        "INSERT INTO Debits (account, transaction_id, balance) VALUES (?1, ?2, ?3)",
-        params![debit_account, transaction_id, balance],
-    )?;
-    tx.execute(
-        "INSERT INTO Credits (account, transaction_id, balance) VALUES (?1, ?2, ?3)",
-        params![credit_account, transaction_id, balance],
-    )?;
+    insert_entries(&tx, transaction_id, debits, credits)?;
 
-    let transaction_result = tx.commit();
+    commit_entries(tx, transaction_id)?;
 
-    match transaction_result {
-        Ok(_) => Ok(transaction_id),
-        Err(_) => panic!("Transaction has failed"),
-    }
+    get_transaction(conn, transaction_id as i32)
 }
 
+// Replaces the whole entry set of a transaction in a single database transaction
 pub fn update_transaction(
-    mut conn: r2d2::PooledConnection<r2d2_sqlite::SqliteConnectionManager>,
+    conn: &mut Connection,
     transaction_id: i32,
-    balance: i32,
     name: &str,
-) -> Result<()> {
-    let con = conn.deref_mut();
-    let tx = con.transaction()?;
+    date: Option<DateTime<Utc>>,
+    debits: &[Posting],
+    credits: &[Posting],
+) -> Result<TransactionV2> {
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+    check_unlocked(&tx, transaction_id)?;
 
     tx.execute(
-        "
-        UPDATE Transactions SET name = ?1 WHERE id = ?2;
-        ",
+        "UPDATE Transactions SET name = ?1 WHERE id = ?2",
         params![name, transaction_id],
     )?;
 
+    if let Some(v) = date {
+        tx.execute(
+            "UPDATE Transactions SET date = ?1 WHERE id = ?2",
+            params![v, transaction_id],
+        )?;
+    }
+
     tx.execute(
-        "
-        UPDATE Credits SET balance = ?1 WHERE transaction_id = ?2;
-        ",
-        params![balance, transaction_id],
+        "DELETE FROM Credits WHERE transaction_id = ?1",
+        params![transaction_id],
     )?;
-
     tx.execute(
-        "
-        UPDATE Debits SET balance = ?1  WHERE transaction_id = ?2;
-        ",
-        params![balance, transaction_id],
+        "DELETE FROM Debits WHERE transaction_id = ?1",
+        params![transaction_id],
     )?;
 
-    tx.commit()
+    insert_entries(&tx, i64::from(transaction_id), debits, credits)?;
+
+    commit_entries(tx, i64::from(transaction_id))?;
+
+    get_transaction(conn, transaction_id)
 }
 
-pub fn remove_transaction(
-    mut conn: r2d2::PooledConnection<r2d2_sqlite::SqliteConnectionManager>,
+// Voids a transaction by recording a reversal with the debits and credits swapped.
+// The reversal references the original, which keeps both in the history
+pub fn void_transaction(
+    conn: &mut Connection,
     id: i32,
-) -> Result<()> {
-    let con = conn.deref_mut();
-    let tx = con.transaction()?;
+    date: DateTime<Utc>,
+) -> Result<TransactionV2> {
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+    let name = check_unlocked(&tx, id)?;
+
+    tx.execute(
+        "INSERT INTO Transactions (date, name, reverses) VALUES (?1, ?2, ?3)",
+        params![date, format!("Void: {}", name.unwrap_or_default()), id],
+    )?;
+
+    let reversal_id = tx.last_insert_rowid();
+
+    tx.execute(
+        "INSERT INTO Debits (account, transaction_id, balance)
+        SELECT account, ?1, balance FROM Credits WHERE transaction_id = ?2",
+        params![reversal_id, id],
+    )?;
+    tx.execute(
+        "INSERT INTO Credits (account, transaction_id, balance)
+        SELECT account, ?1, balance FROM Debits WHERE transaction_id = ?2",
+        params![reversal_id, id],
+    )?;
+
+    commit_entries(tx, reversal_id)?;
+
+    get_transaction(conn, reversal_id as i32)
+}
+
+// Voided pairs are kept for the audit trail and can not be deleted
+pub fn remove_transaction(conn: &mut Connection, id: i32) -> Result<()> {
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+    check_unlocked(&tx, id)?;
 
     tx.execute("DELETE FROM Transactions WHERE id = ?1", params![id])?;
 
-    tx.commit()
+    tx.commit()?;
+
+    Ok(())
 }
 
 // List database functions
 
-pub fn list_transactions(
-    conn: r2d2::PooledConnection<r2d2_sqlite::SqliteConnectionManager>,
-) -> Result<Vec<Transaction>> {
-    let mut stmt = conn.prepare("SELECT id, date, name from Transactions ORDER BY date DESC")?;
+pub fn list_transactions(conn: &Connection, include_voided: bool) -> Result<Vec<Transaction>> {
+    let mut stmt = conn.prepare(
+        "SELECT t.id, t.date, t.name, EXISTS(SELECT 1 FROM Transactions as r WHERE r.reverses = t.id), t.reverses
+        FROM Transactions as t
+        WHERE (?1 OR (t.reverses IS NULL AND NOT EXISTS(SELECT 1 FROM Transactions as r WHERE r.reverses = t.id)))
+        ORDER BY t.date DESC",
+    )?;
 
     let transactions = stmt
-        .query_map(NO_PARAMS, |row| {
+        .query_map(params![include_voided], |row| {
             Ok(Transaction {
-                id: row.get(0).unwrap(),
-                date: row.get(1).unwrap(),
-                name: row.get(2).unwrap(),
+                id: row.get(0)?,
+                date: row.get(1)?,
+                name: row.get(2)?,
+                voided: row.get(3)?,
+                reverses: row.get(4)?,
             })
-        })
-        .and_then(|mapped_rows| {
-            Ok(mapped_rows
-                .map(|row| row.unwrap())
-                .collect::<Vec<Transaction>>())
-        })?;
+        })?
+        .collect::<rusqlite::Result<Vec<Transaction>>>()?;
 
     Ok(transactions)
 }
 
 // TODO: I'm keeping this function for now because I might need to reuse the SQL
-pub fn get_entries_for_transaction(
-    conn: r2d2::PooledConnection<r2d2_sqlite::SqliteConnectionManager>,
-    id: i32,
-) -> Result<Vec<Entry>> {
+pub fn get_entries_for_transaction(conn: &Connection, id: i32) -> Result<Vec<Entry>> {
     let mut stmt = conn.prepare(
         "
         SELECT c.id, c.account, a.name, c.transaction_id, c.balance, 0 as entry_type FROM Credits as c INNER JOIN Accounts as a ON c.account = a.id WHERE c.transaction_id = ?1
@@ -168,75 +290,73 @@ pub fn get_entries_for_transaction(
     let result = stmt
         .query_map(params![id], |row| {
             Ok(Entry {
-                id: row.get(0).unwrap(),
-                account: row.get(1).unwrap(),
-                account_name: row.get(2).unwrap(),
-                transaction_id: row.get(3).unwrap(),
-                balance: row.get(4).unwrap(),
-                entry_type: EntryType::from_i32(row.get(5).unwrap()),
+                id: row.get(0)?,
+                account: row.get(1)?,
+                account_name: row.get(2)?,
+                transaction_id: row.get(3)?,
+                balance: row.get(4)?,
+                entry_type: row.get(5)?,
             })
-        })
-        .and_then(|mapped_rows| Ok(mapped_rows.map(|row| row.unwrap()).collect::<Vec<Entry>>()))?;
-
-    if result.len() % 2 != 0 {
-        panic!("Uneven number of entries. Integrity damaged");
-    }
+        })?
+        .collect::<rusqlite::Result<Vec<Entry>>>()?;
 
     Ok(result)
 }
 
 pub fn list_transactions_date(
-    conn: r2d2::PooledConnection<r2d2_sqlite::SqliteConnectionManager>,
+    conn: &Connection,
     year: i32,
     month: u8,
+    include_voided: bool,
 ) -> Result<Vec<Transaction>> {
     let mut stmt = conn.prepare(
-        "SELECT id, date, name from Transactions
-        WHERE CAST(strftime('%m', date) as integer) = ?1 
-        AND CAST(strftime('%Y', date) as integer) = ?2 
-        ORDER BY date DESC",
+        "SELECT t.id, t.date, t.name, EXISTS(SELECT 1 FROM Transactions as r WHERE r.reverses = t.id), t.reverses
+        FROM Transactions as t
+        WHERE CAST(strftime('%m', t.date) as integer) = ?1 
+        AND CAST(strftime('%Y', t.date) as integer) = ?2 
+        AND (?3 OR (t.reverses IS NULL AND NOT EXISTS(SELECT 1 FROM Transactions as r WHERE r.reverses = t.id)))
+        ORDER BY t.date DESC",
     )?;
 
     let transactions = stmt
-        .query_map(params![month, year], |row| {
+        .query_map(params![month, year, include_voided], |row| {
             Ok(Transaction {
-                id: row.get(0).unwrap(),
-                date: row.get(1).unwrap(),
-                name: row.get(2).unwrap(),
+                id: row.get(0)?,
+                date: row.get(1)?,
+                name: row.get(2)?,
+                voided: row.get(3)?,
+                reverses: row.get(4)?,
             })
-        })
-        .and_then(|mapped_rows| {
-            Ok(mapped_rows
-                .map(|row| row.unwrap())
-                .collect::<Vec<Transaction>>())
-        })?;
+        })?
+        .collect::<rusqlite::Result<Vec<Transaction>>>()?;
 
     Ok(transactions)
 }
 
 pub fn list_transactions_year(
-    conn: r2d2::PooledConnection<r2d2_sqlite::SqliteConnectionManager>,
+    conn: &Connection,
     year: i32,
+    include_voided: bool,
 ) -> Result<Vec<Transaction>> {
     let mut stmt = conn.prepare(
-        "SELECT id, date, name from Transactions
-        WHERE CAST(strftime('%Y', date) as integer) = ?1 
-        ORDER BY date DESC",
+        "SELECT t.id, t.date, t.name, EXISTS(SELECT 1 FROM Transactions as r WHERE r.reverses = t.id), t.reverses
+        FROM Transactions as t
+        WHERE CAST(strftime('%Y', t.date) as integer) = ?1 
+        AND (?2 OR (t.reverses IS NULL AND NOT EXISTS(SELECT 1 FROM Transactions as r WHERE r.reverses = t.id)))
+        ORDER BY t.date DESC",
     )?;
 
     let transactions = stmt
-        .query_map(params![year], |row| {
+        .query_map(params![year, include_voided], |row| {
             Ok(Transaction {
-                id: row.get(0).unwrap(),
-                date: row.get(1).unwrap(),
-                name: row.get(2).unwrap(),
+                id: row.get(0)?,
+                date: row.get(1)?,
+                name: row.get(2)?,
+                voided: row.get(3)?,
+                reverses: row.get(4)?,
             })
-        })
-        .and_then(|mapped_rows| {
-            Ok(mapped_rows
-                .map(|row| row.unwrap())
-                .collect::<Vec<Transaction>>())
-        })?;
+        })?
+        .collect::<rusqlite::Result<Vec<Transaction>>>()?;
 
     Ok(transactions)
 }
@@ -244,88 +364,589 @@ pub fn list_transactions_year(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::transaction::data::EntryType;
+    use chrono::TimeZone;
     use r2d2_sqlite::SqliteConnectionManager;
     use rusqlite::params;
 
-    fn create_base(conn: r2d2::PooledConnection<r2d2_sqlite::SqliteConnectionManager>) {
-        let _ = conn.execute(
-            "CREATE TABLE \"Currency\" (
-            \"code\"	TEXT NOT NULL UNIQUE,
-            \"numeric_code\"	INTEGER NOT NULL UNIQUE,
-            \"minor_unit\"	INTEGER NOT NULL DEFAULT 2,
-            \"name\"	TEXT NOT NULL UNIQUE,
-            PRIMARY KEY(\"code\")
-            )",
-            params![],
+    fn create_base(conn: &Connection) {
+        conn.execute_batch(
+            "INSERT INTO Accounts (type, name, currency) VALUES (0, 'Current', 'GBP');
+            INSERT INTO Accounts (type, name, currency) VALUES (1, 'Expenses', 'GBP');",
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn create_transaction_test() {
+        let pool = crate::migrations::memory_pool();
+        create_base(&pool.get().unwrap());
+
+        let debits = vec![Posting {
+            account: 1,
+            balance: 50,
+        }];
+        let credits = vec![Posting {
+            account: 2,
+            balance: 50,
+        }];
+
+        let transaction = create_transaction(
+            &mut pool.get().unwrap(),
+            "Super Payment",
+            Utc::now(),
+            &debits,
+            &credits,
         );
-        let _num = conn.execute(
-            "INSERT INTO Currency (code, numeric_code, minor_unit, name) VALUES ('GBP', '826', '2', 'Pound Sterling');",
+
+        assert_eq!(transaction.unwrap().id(), 1);
+    }
+
+    #[test]
+    fn create_split_transaction_stores_every_leg() {
+        let pool = crate::migrations::memory_pool();
+        create_base(&pool.get().unwrap());
+
+        let _num = pool.get().unwrap().execute(
+            "INSERT INTO Accounts (type, name, currency) VALUES (4, \"Household\", \"GBP\")",
             params![],
         );
 
-        let _ = conn.execute(
-            "CREATE TABLE \"Accounts\" (
-	        \"id\"	INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
-	        \"type\"	INTEGER NOT NULL,
-	        \"name\"	TEXT NOT NULL,
-	        \"currency\"	TEXT NOT NULL,
-	        FOREIGN KEY(\"currency\") REFERENCES \"Currency\"(\"code\")
-            )",
-            params![],
+        let debits = vec![
+            Posting {
+                account: 2,
+                balance: 3000,
+            },
+            Posting {
+                account: 3,
+                balance: 1250,
+            },
+        ];
+        let credits = vec![Posting {
+            account: 1,
+            balance: 4250,
+        }];
+
+        let transaction = create_transaction(
+            &mut pool.get().unwrap(),
+            "Weekly shop",
+            Utc::now(),
+            &debits,
+            &credits,
+        )
+        .unwrap();
+
+        assert_eq!(transaction.name(), "Weekly shop");
+        assert_eq!(transaction.entries().len(), 3);
+
+        let debit_total: i64 = transaction
+            .entries()
+            .iter()
+            .filter(|e| e.entry_type() == EntryType::Debit)
+            .map(|e| e.balance().amount)
+            .sum();
+        let credit_total: i64 = transaction
+            .entries()
+            .iter()
+            .filter(|e| e.entry_type() == EntryType::Credit)
+            .map(|e| e.balance().amount)
+            .sum();
+
+        assert_eq!(debit_total, 4250);
+        assert_eq!(credit_total, 4250);
+    }
+
+    #[test]
+    fn backdated_transaction_is_listed_in_its_own_month() {
+        let pool = crate::migrations::memory_pool();
+        create_base(&pool.get().unwrap());
+
+        let debits = vec![Posting {
+            account: 2,
+            balance: 1999,
+        }];
+        let credits = vec![Posting {
+            account: 1,
+            balance: 1999,
+        }];
+        let date = Utc.ymd(2020, 1, 31).and_hms(23, 0, 0);
+
+        let transaction = create_transaction(
+            &mut pool.get().unwrap(),
+            "Card payment",
+            date,
+            &debits,
+            &credits,
+        )
+        .unwrap();
+
+        assert_eq!(transaction.date(), date);
+
+        let january = list_transactions_date(&pool.get().unwrap(), 2020, 1, true).unwrap();
+        let february = list_transactions_date(&pool.get().unwrap(), 2020, 2, true).unwrap();
+
+        assert_eq!(january.len(), 1);
+        assert_eq!(february.len(), 0);
+    }
+
+    #[test]
+    fn update_transaction_can_change_the_date() {
+        let pool = crate::migrations::memory_pool();
+        create_base(&pool.get().unwrap());
+
+        let debits = vec![Posting {
+            account: 2,
+            balance: 500,
+        }];
+        let credits = vec![Posting {
+            account: 1,
+            balance: 500,
+        }];
+        let created = Utc.ymd(2020, 3, 1).and_hms(12, 0, 0);
+        let moved = Utc.ymd(2020, 2, 28).and_hms(12, 0, 0);
+
+        let _ = create_transaction(
+            &mut pool.get().unwrap(),
+            "Statement",
+            created,
+            &debits,
+            &credits,
         );
+        let result = update_transaction(
+            &mut pool.get().unwrap(),
+            1,
+            "Statement",
+            Some(moved),
+            &debits,
+            &credits,
+        );
+
+        assert_eq!(result.unwrap().date(), moved);
+    }
+
+    #[test]
+    fn update_transaction_replaces_the_entry_set() {
+        let pool = crate::migrations::memory_pool();
+        create_base(&pool.get().unwrap());
 
-        let _num = conn.execute(
-            "INSERT INTO Accounts (type, name, currency) VALUES (0, \"Current\", \"GBP\")",
+        let _num = pool.get().unwrap().execute(
+            "INSERT INTO Accounts (type, name, currency) VALUES (4, \"Household\", \"GBP\")",
             params![],
         );
 
-        let _num = conn.execute(
-            "INSERT INTO Accounts (type, name, currency) VALUES (1, \"Expenses\", \"GBP\")",
-            params![],
+        let debits = vec![Posting {
+            account: 2,
+            balance: 1000,
+        }];
+        let credits = vec![Posting {
+            account: 1,
+            balance: 1000,
+        }];
+        let _ = create_transaction(
+            &mut pool.get().unwrap(),
+            "Shop",
+            Utc::now(),
+            &debits,
+            &credits,
         );
 
-        let _ = conn.execute(
-            "CREATE TABLE \"Transactions\" (
-	        \"id\"	INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
-	        \"date\"	TEXT NOT NULL,
-	        \"name\"	TEXT
-            )",
-            params![],
+        let new_debits = vec![
+            Posting {
+                account: 2,
+                balance: 700,
+            },
+            Posting {
+                account: 3,
+                balance: 500,
+            },
+        ];
+        let new_credits = vec![Posting {
+            account: 1,
+            balance: 1200,
+        }];
+
+        let transaction = update_transaction(
+            &mut pool.get().unwrap(),
+            1,
+            "Shop and household",
+            None,
+            &new_debits,
+            &new_credits,
+        )
+        .unwrap();
+
+        assert_eq!(transaction.name(), "Shop and household");
+        assert_eq!(transaction.entries().len(), 3);
+        assert!(transaction
+            .entries()
+            .iter()
+            .any(|e| e.account() == 3 && e.balance().amount == 500));
+        assert!(transaction
+            .entries()
+            .iter()
+            .any(|e| e.entry_type() == EntryType::Credit && e.balance().amount == 1200));
+    }
+
+    #[test]
+    fn update_missing_transaction_returns_no_rows() {
+        let pool = crate::migrations::memory_pool();
+        create_base(&pool.get().unwrap());
+
+        let debits = vec![Posting {
+            account: 2,
+            balance: 100,
+        }];
+        let credits = vec![Posting {
+            account: 1,
+            balance: 100,
+        }];
+
+        let result = update_transaction(
+            &mut pool.get().unwrap(),
+            42,
+            "Ghost",
+            None,
+            &debits,
+            &credits,
         );
 
-        let _ = conn.execute(
-            "CREATE TABLE \"Credits\" (
-	        \"id\"	INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
-	        \"account\"	INTEGER NOT NULL,
-	        \"transaction_id\"	INTEGER NOT NULL,
-	        \"balance\"	INTEGER NOT NULL DEFAULT 0 CHECK (typeof(\"balance\") = 'integer'),
-	        FOREIGN KEY(\"account\") REFERENCES \"Accounts\"(\"id\"),
-	        FOREIGN KEY(\"transaction_id\") REFERENCES \"Transactions\"(\"id\") ON DELETE CASCADE
-            )",
-            params![],
+        assert_eq!(result.unwrap_err().to_string(), "Transaction 42 not found");
+        assert_eq!(
+            list_transactions(&pool.get().unwrap(), true).unwrap().len(),
+            0
         );
+    }
 
-        let _ = conn.execute(
-            "CREATE TABLE \"Debits\" (
-	        \"id\"	INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
-	        \"account\"	INTEGER NOT NULL,
-	        \"transaction_id\"	INTEGER NOT NULL,
-	        \"balance\"	INTEGER NOT NULL DEFAULT 0 CHECK (typeof(\"balance\") = 'integer'),
-	        FOREIGN KEY(\"account\") REFERENCES \"Accounts\"(\"id\"),
-	        FOREIGN KEY(\"transaction_id\") REFERENCES \"Transactions\"(\"id\") ON DELETE CASCADE
-            )",
-            params![],
+    #[test]
+    fn void_transaction_records_a_swapped_reversal() {
+        let pool = crate::migrations::memory_pool();
+        create_base(&pool.get().unwrap());
+
+        let debits = vec![Posting {
+            account: 2,
+            balance: 750,
+        }];
+        let credits = vec![Posting {
+            account: 1,
+            balance: 750,
+        }];
+        let _ = create_transaction(
+            &mut pool.get().unwrap(),
+            "Takeaway",
+            Utc::now(),
+            &debits,
+            &credits,
         );
+
+        let reversal = void_transaction(&mut pool.get().unwrap(), 1, Utc::now()).unwrap();
+
+        assert_eq!(reversal.name(), "Void: Takeaway");
+        assert_eq!(reversal.reverses(), Some(1));
+        assert!(reversal
+            .entries()
+            .iter()
+            .any(|e| e.account() == 1 && e.entry_type() == EntryType::Debit));
+        assert!(reversal
+            .entries()
+            .iter()
+            .any(|e| e.account() == 2 && e.entry_type() == EntryType::Credit));
+
+        let original = get_transaction(&pool.get().unwrap(), 1).unwrap();
+
+        assert!(original.voided());
+        assert_eq!(original.voided_by(), Some(reversal.id()));
+        assert!(original.locked());
     }
 
     #[test]
-    fn create_transaction_test() {
-        let manager = SqliteConnectionManager::memory();
-        let pool = r2d2::Pool::new(manager).unwrap();
-        create_base(pool.get().unwrap());
+    fn a_transaction_is_voided_once() {
+        let pool = crate::migrations::memory_pool();
+        create_base(&pool.get().unwrap());
+
+        let debits = vec![Posting {
+            account: 2,
+            balance: 750,
+        }];
+        let credits = vec![Posting {
+            account: 1,
+            balance: 750,
+        }];
+        let _ = create_transaction(
+            &mut pool.get().unwrap(),
+            "Takeaway",
+            Utc::now(),
+            &debits,
+            &credits,
+        );
+        let reversal = void_transaction(&mut pool.get().unwrap(), 1, Utc::now()).unwrap();
+
+        for id in &[1, reversal.id()] {
+            match void_transaction(&mut pool.get().unwrap(), *id, Utc::now()) {
+                Err(LedgerError::Conflict(_)) => {}
+                other => panic!("Expected a conflict, got {:?}", other),
+            }
+        }
+
+        match void_transaction(&mut pool.get().unwrap(), 99, Utc::now()) {
+            Err(LedgerError::NotFound(m)) => assert_eq!(m, "Transaction 99 not found"),
+            other => panic!("Expected not found, got {:?}", other),
+        }
+
+        let second = pool.get().unwrap().execute(
+            "INSERT INTO Transactions (date, name, reverses) VALUES (?1, 'Void again', 1)",
+            params![Utc::now()],
+        );
+        assert!(second.is_err());
+    }
+
+    #[test]
+    fn listing_can_hide_voided_pairs() {
+        let pool = crate::migrations::memory_pool();
+        create_base(&pool.get().unwrap());
+
+        let debits = vec![Posting {
+            account: 2,
+            balance: 100,
+        }];
+        let credits = vec![Posting {
+            account: 1,
+            balance: 100,
+        }];
+        let date = Utc.ymd(2020, 5, 4).and_hms(10, 0, 0);
+        let _ = create_transaction(&mut pool.get().unwrap(), "Kept", date, &debits, &credits);
+        let _ = create_transaction(&mut pool.get().unwrap(), "Mistake", date, &debits, &credits);
+        let _ = void_transaction(&mut pool.get().unwrap(), 2, date);
+
+        let all = list_transactions(&pool.get().unwrap(), true).unwrap();
+        let visible = list_transactions(&pool.get().unwrap(), false).unwrap();
+        let visible_month = list_transactions_date(&pool.get().unwrap(), 2020, 5, false).unwrap();
+        let visible_year = list_transactions_year(&pool.get().unwrap(), 2020, false).unwrap();
+
+        assert_eq!(all.len(), 3);
+        assert_eq!(all.iter().filter(|t| t.voided).count(), 1);
+        assert_eq!(visible.len(), 1);
+        assert_eq!(visible[0].name, "Kept");
+        assert_eq!(visible_month.len(), 1);
+        assert_eq!(visible_year.len(), 1);
+    }
 
-        let id = create_transaction(pool.get().unwrap(), 1, 2, 50, "Super Payment");
+    // The base schema with the ledger constraints and foreign keys enforced
+    // A ledger with a second currency to check the constraints against
+    fn create_constrained() -> r2d2::Pool<SqliteConnectionManager> {
+        let pool = crate::migrations::memory_pool();
+        create_base(&pool.get().unwrap());
+
+        pool.get()
+            .unwrap()
+            .execute(
+                "INSERT INTO Accounts (type, name, currency) VALUES (0, 'Euro Current', 'EUR')",
+                params![],
+            )
+            .unwrap();
+
+        pool
+    }
+
+    #[test]
+    fn database_rejects_unbalanced_transaction() {
+        let pool = create_constrained();
+
+        let debits = vec![Posting {
+            account: 1,
+            balance: 100,
+        }];
+        let credits = vec![Posting {
+            account: 2,
+            balance: 90,
+        }];
+
+        let result = create_transaction(
+            &mut pool.get().unwrap(),
+            "Short",
+            Utc::now(),
+            &debits,
+            &credits,
+        );
+
+        match result {
+            Err(LedgerError::Integrity(_)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        assert!(list_transactions(&pool.get().unwrap(), true)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn database_rejects_currencies_that_do_not_balance() {
+        let pool = create_constrained();
+
+        let debits = vec![Posting {
+            account: 3,
+            balance: 100,
+        }];
+        let credits = vec![Posting {
+            account: 1,
+            balance: 100,
+        }];
+
+        let result = create_transaction(
+            &mut pool.get().unwrap(),
+            "Holiday",
+            Utc::now(),
+            &debits,
+            &credits,
+        );
+
+        assert!(result.is_err());
+        assert!(list_transactions(&pool.get().unwrap(), true)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn database_accepts_currencies_balanced_through_fx_accounts() {
+        let pool = create_constrained();
+        pool.get()
+            .unwrap()
+            .execute_batch(
+                "INSERT INTO Accounts (type, name, currency) VALUES
+                    (2, 'FX Sterling', 'GBP'), (2, 'FX Euro', 'EUR');",
+            )
+            .unwrap();
+
+        let debits = vec![
+            Posting {
+                account: 3,
+                balance: 115,
+            },
+            Posting {
+                account: 4,
+                balance: 100,
+            },
+        ];
+        let credits = vec![
+            Posting {
+                account: 1,
+                balance: 100,
+            },
+            Posting {
+                account: 5,
+                balance: 115,
+            },
+        ];
+
+        let created = create_transaction(
+            &mut pool.get().unwrap(),
+            "Transfer",
+            Utc::now(),
+            &debits,
+            &credits,
+        )
+        .unwrap();
+
+        assert_eq!(created.entries().len(), 4);
+    }
+
+    #[test]
+    fn database_accepts_balanced_changes_and_cascades_deletes() {
+        let pool = create_constrained();
+
+        let debits = vec![Posting {
+            account: 1,
+            balance: 100,
+        }];
+        let credits = vec![Posting {
+            account: 2,
+            balance: 100,
+        }];
+        let moved = vec![
+            Posting {
+                account: 1,
+                balance: 60,
+            },
+            Posting {
+                account: 1,
+                balance: 40,
+            },
+        ];
+
+        let created = create_transaction(
+            &mut pool.get().unwrap(),
+            "Lunch",
+            Utc::now(),
+            &debits,
+            &credits,
+        )
+        .unwrap();
+        update_transaction(
+            &mut pool.get().unwrap(),
+            created.id(),
+            "Lunch",
+            None,
+            &moved,
+            &credits,
+        )
+        .unwrap();
+        void_transaction(&mut pool.get().unwrap(), created.id(), Utc::now()).unwrap();
+
+        match remove_transaction(&mut pool.get().unwrap(), created.id()) {
+            Err(LedgerError::Conflict(_)) => {}
+            other => panic!("Expected a conflict, got {:?}", other),
+        }
+
+        let conn = pool.get().unwrap();
+        conn.execute(
+            "DELETE FROM Transactions WHERE id = ?1",
+            params![created.id()],
+        )
+        .unwrap();
+        let entries: i32 = conn
+            .query_row(
+                "SELECT (SELECT COUNT(*) FROM Debits) + (SELECT COUNT(*) FROM Credits)",
+                params![],
+                |row| row.get(0),
+            )
+            .unwrap();
+        let violations: i32 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM TransactionViolations",
+                params![],
+                |row| row.get(0),
+            )
+            .unwrap();
+
+        // Deleting the original also deletes its reversal
+        assert_eq!(entries, 0);
+        assert_eq!(violations, 0);
+    }
+
+    #[test]
+    fn database_locks_currency_of_accounts_with_entries() {
+        let pool = create_constrained();
+
+        let debits = vec![Posting {
+            account: 1,
+            balance: 100,
+        }];
+        let credits = vec![Posting {
+            account: 2,
+            balance: 100,
+        }];
+        create_transaction(
+            &mut pool.get().unwrap(),
+            "Lunch",
+            Utc::now(),
+            &debits,
+            &credits,
+        )
+        .unwrap();
+
+        let result = pool.get().unwrap().execute(
+            "UPDATE Accounts SET currency = 'EUR' WHERE id = 1",
+            params![],
+        );
 
-        assert_eq!(id.unwrap(), 1);
+        assert!(result.is_err());
     }
 }
@@ -1,9 +1,10 @@
 mod api;
 pub mod data;
-mod db;
+pub mod db;
 
 pub use self::api::create_transaction;
 pub use self::api::delete_transaction;
 pub use self::api::get_transaction;
 pub use self::api::list_transactions;
 pub use self::api::update_transaction;
+pub use self::api::void_transaction;